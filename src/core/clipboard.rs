@@ -0,0 +1,121 @@
+//! Clipboard support. Copied text is sent to the system clipboard with
+//! an OSC 52 escape sequence, which the terminal handles (even over
+//! SSH), or piped to an external command. The last copied text is
+//! also kept in an internal register, used for pasting when there is
+//! no external command to read the system clipboard.
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use base64;
+use futures::sync::mpsc::UnboundedSender;
+
+pub struct Clipboard {
+    /// Whether to set the system clipboard with OSC 52 escape sequences.
+    osc52: bool,
+
+    /// Shell command the copied text is piped to, when OSC 52 is
+    /// disabled.
+    copy_cmd: Option<String>,
+
+    /// Shell command that prints the content of the system clipboard.
+    paste_cmd: Option<String>,
+
+    /// The last copied text.
+    register: String,
+
+    /// OSC 52 sequence waiting to be written to the terminal.
+    pending: Option<String>,
+}
+
+impl Clipboard {
+    pub fn new(osc52: bool, copy_cmd: Option<String>, paste_cmd: Option<String>) -> Self {
+        Clipboard {
+            osc52,
+            copy_cmd,
+            paste_cmd,
+            register: String::new(),
+            pending: None,
+        }
+    }
+
+    /// Put text on the clipboard.
+    pub fn copy(&mut self, text: String) {
+        if self.osc52 {
+            self.pending = Some(format!("\x1b]52;c;{}\x07", base64::encode(&text)));
+        } else if let Some(ref cmd) = self.copy_cmd {
+            let cmd = cmd.clone();
+            let text = text.clone();
+            thread::spawn(move || {
+                if let Err(e) = run_copy_cmd(&cmd, &text) {
+                    error!("failed to run clipboard command \"{}\": {}", cmd, e);
+                }
+            });
+        }
+        self.register = text;
+    }
+
+    /// Get the text to paste, from the system clipboard if we can read
+    /// it, or from the internal register otherwise. Reading the system
+    /// clipboard may take a while, so the paste command runs in another
+    /// thread, and the text is sent into `pasted` instead of being
+    /// returned.
+    pub fn paste(&self, pasted: UnboundedSender<String>) -> Option<String> {
+        let cmd = match self.paste_cmd {
+            Some(ref cmd) => cmd.clone(),
+            None => return Some(self.register.clone()),
+        };
+        let register = self.register.clone();
+        thread::spawn(move || {
+            let text = run_paste_cmd(&cmd).unwrap_or_else(|e| {
+                error!("failed to run clipboard command \"{}\": {}", cmd, e);
+                register
+            });
+            pasted
+                .unbounded_send(text)
+                .unwrap_or_else(|e| error!("failed to send pasted text: {:?}", e));
+        });
+        None
+    }
+
+    /// Write the pending OSC 52 sequence, if any, to the terminal.
+    pub fn flush<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        if let Some(seq) = self.pending.take() {
+            w.write_all(seq.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn run_copy_cmd(cmd: &str, text: &str) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Dropping stdin closes the pipe, so that the command doesn't wait
+    // for more input.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    child.wait()?;
+    Ok(())
+}
+
+fn run_paste_cmd(cmd: &str) -> io::Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut text = String::new();
+    if let Some(ref mut stdout) = child.stdout {
+        stdout.read_to_string(&mut text)?;
+    }
+    child.wait()?;
+    Ok(text)
+}
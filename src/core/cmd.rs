@@ -3,16 +3,30 @@
 /// currently commands can only be input through the CommandPrompt. Vim style.
 use xrl::ViewId;
 
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug)]
+use core::{quote, split_commands, tokenize, Range, SearchQuery, Setting, Word};
+
+/// The largest repeat count. Larger counts would keep the editor busy
+/// for too long.
+pub const MAX_COUNT: usize = 10_000;
+
+#[derive(Debug, Clone)]
 pub enum Command {
     /// Close the CommandPrompt.
     Cancel,
     /// Quit editor.
     Quit,
-    /// Save the current file buffer.
-    Save(Option<ViewId>),
+    /// Quit editor, discarding the unsaved changes.
+    ForceQuit,
+    /// Save the current file buffer, optionally under a new file name.
+    Save(Option<ViewId>, Option<String>),
+    /// Save all the buffers that have a file name.
+    SaveAll,
+    /// Save the current file buffer, optionally under a new file name,
+    /// and quit.
+    WriteQuit(Option<String>),
     /// Backspace
     Back,
     /// Delete
@@ -23,6 +37,13 @@ pub enum Command {
     NextBuffer,
     /// Cycle to the previous buffer.
     PrevBuffer,
+    /// Switch to the buffer with the given name.
+    SwitchBuffer(String),
+    /// List the open buffers.
+    ListBuffers,
+    /// Close a buffer, given its number, or the current one. Buffers
+    /// with unsaved changes are only closed if forced.
+    Close(Option<usize>, bool),
     /// Move cursor left.
     MoveLeft,
     /// Move cursor right.
@@ -31,26 +52,139 @@ pub enum Command {
     MoveUp,
     /// Move cursor down.
     MoveDown,
+    /// Extend the selection one position left.
+    SelectLeft,
+    /// Extend the selection one position right.
+    SelectRight,
+    /// Extend the selection one line up.
+    SelectUp,
+    /// Extend the selection one line down.
+    SelectDown,
+    /// Extend the selection one word left.
+    SelectWordLeft,
+    /// Extend the selection one word right.
+    SelectWordRight,
+    /// Extend the selection to the start of the line.
+    SelectHome,
+    /// Extend the selection to the end of the line.
+    SelectEnd,
+    /// Extend the selection one page up.
+    SelectPageUp,
+    /// Extend the selection one page down.
+    SelectPageDown,
+    /// Extend the selection to the start of the document.
+    SelectDocumentStart,
+    /// Extend the selection to the end of the document.
+    SelectDocumentEnd,
+    /// Move the cursor one word left.
+    MoveWordLeft,
+    /// Move the cursor one word right.
+    MoveWordRight,
+    /// Move the cursor to the start of the paragraph.
+    ParagraphUp,
+    /// Move the cursor to the end of the paragraph.
+    ParagraphDown,
+    /// Move the cursor to the start of the document.
+    DocumentStart,
+    /// Move the cursor to the end of the document.
+    DocumentEnd,
+    /// Move the cursor to a line, and optionally a column (both
+    /// starting at 1).
+    Goto(u64, Option<u64>),
+    /// Add a cursor on the line above.
+    AddCursorAbove,
+    /// Add a cursor on the line below.
+    AddCursorBelow,
+    /// Add the next occurrence of the current word or selection to the selections.
+    SelectNextOccurrence,
+    /// Select all the occurrences of the current word or selection.
+    SelectAllOccurrences,
+    /// Go back to a single cursor.
+    CollapseSelections,
     /// Page down
     PageDown,
     /// Page up
     PageUp,
     /// Change the syntax theme.
     SetTheme(String),
+    /// Change the language of the current buffer, or show it.
+    SetLanguage(Option<String>),
     /// Toggle displaying line numbers.
     ToggleLineNumbers,
+    /// Copy the selection to the clipboard.
+    Copy,
+    /// Cut the selection to the clipboard.
+    Cut,
+    /// Paste the clipboard content.
+    Paste,
+    /// Undo the last edit.
+    Undo,
+    /// Redo the last undone edit.
+    Redo,
+    /// Run a command the given number of times.
+    Repeat(usize, Box<Command>),
+    /// Start recording a macro in the given register.
+    Record(String),
+    /// Stop recording a macro.
+    StopRecording,
+    /// Play the macro in the given register, the given number of times.
+    Play(String, usize),
+    /// Show the help of a command, or the list of commands.
+    Help(Option<String>),
+    /// Search for text, and move to the nearest match.
+    Search(SearchQuery),
+    /// Move to the next match of the search.
+    SearchNext,
+    /// Move to the previous match of the search.
+    SearchPrev,
+    /// Stop highlighting the matches of the search.
+    NoHighlight,
+    /// Replace the matches of a search, either all at once or after
+    /// asking for each of them.
+    Replace(SearchQuery, String, bool),
+    /// Change an option, or show its value, for the current buffer or
+    /// for all of them.
+    Set(Setting, bool),
+    /// List the plugins of the current buffer, and whether they run.
+    ListPlugins,
+    /// Start or stop a plugin for the current buffer.
+    Plugin(PluginAction, String),
+    /// Run a command provided by a plugin of the current buffer.
+    PluginCmd(String),
+    /// Delete the selected lines.
+    DeleteLines,
+    /// Sort the selected lines.
+    Sort,
+    /// Indent the selected lines.
+    Indent,
+    /// Outdent the selected lines.
+    Outdent,
+    /// Select a range of lines, and run a command on them.
+    Ranged(Range, Box<Command>),
+    /// Run several commands, one after the other.
+    Sequence(Vec<Command>),
+}
+
+/// What the `plugin` command does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PluginAction {
+    Start,
+    Stop,
+}
+
+impl fmt::Display for PluginAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PluginAction::Start => write!(f, "start"),
+            PluginAction::Stop => write!(f, "stop"),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ParseCommandError {
-    /// Didnt expect a command to take an argument.
-    UnexpectedArgument,
-    /// The given command expected an argument.
-    ExpectedArgument {
-        cmd: String,
-        expected: usize,
-        found: usize,
-    },
+    /// A required argument is missing.
+    ExpectedArgument { cmd: String, arg: &'static str },
     /// The given command was given to many arguments.
     TooManyArguments {
         cmd: String,
@@ -59,60 +193,1363 @@ pub enum ParseCommandError {
     },
     /// Invalid input was received.
     UnknownCommand(String),
+    /// A quoted argument is missing its closing quote.
+    UnterminatedQuote,
+    /// The count prefixing a command is not a valid repeat count.
+    InvalidCount(String),
+    /// An argument given to the command is not valid.
+    InvalidArgument {
+        cmd: String,
+        arg: &'static str,
+        value: String,
+        reason: String,
+    },
+    /// The range of lines prefixing a command is not valid.
+    InvalidRange { range: String, reason: &'static str },
+    /// A range of lines was given to a command that doesn't take one.
+    UnexpectedRange(String),
+    /// The argument of `set` is not valid.
+    InvalidSetting { setting: String, reason: String },
+}
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseCommandError::ExpectedArgument { ref cmd, arg } => {
+                write!(f, "{}: missing argument <{}>", cmd, arg)
+            }
+            ParseCommandError::TooManyArguments {
+                ref cmd,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected at most {} argument(s), found {}",
+                cmd, expected, found
+            ),
+            ParseCommandError::UnknownCommand(ref cmd) => write!(f, "unknown command: {}", cmd),
+            ParseCommandError::UnterminatedQuote => write!(f, "missing closing quote"),
+            ParseCommandError::InvalidCount(ref count) => {
+                write!(
+                    f,
+                    "invalid count {}: expected a number from 1 to {}",
+                    count, MAX_COUNT
+                )
+            }
+            ParseCommandError::InvalidArgument {
+                ref cmd,
+                arg,
+                ref value,
+                ref reason,
+            } => write!(f, "{}: invalid {} \"{}\": {}", cmd, arg, value, reason),
+            ParseCommandError::InvalidRange { ref range, reason } => {
+                write!(f, "invalid range {}: {}", range, reason)
+            }
+            ParseCommandError::UnexpectedRange(ref cmd) => {
+                write!(f, "{}: this command does not take a range", cmd)
+            }
+            ParseCommandError::InvalidSetting {
+                ref setting,
+                ref reason,
+            } => write!(f, "invalid setting {}: {}", setting, reason),
+        }
+    }
+}
+
+/// Type of the value an argument takes. It determines how the argument
+/// is parsed, and what it can be completed with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    /// A path to a file.
+    File,
+    /// The name of a syntax theme.
+    Theme,
+    /// A `<line>[:<column>]` position, where both numbers start at 1.
+    Position,
+    /// The name of a macro register.
+    Register,
+    /// A positive number.
+    Count,
+    /// The name of a command.
+    Command,
+    /// The name of an open buffer.
+    Buffer,
+    /// Any text, e.g. to search for.
+    Text,
+    /// Search flags: `c` for a case sensitive search, `w` to match
+    /// whole words only and `r` for a regex.
+    SearchFlags,
+    /// An option and its new value, e.g. `tab_size=2`, or an option
+    /// followed by `?` to show its value.
+    Setting,
+    /// `start` or `stop`.
+    PluginAction,
+    /// The name of a plugin.
+    Plugin,
+    /// The name of a language known to the core.
+    Language,
+}
+
+/// Description of a command argument.
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+/// Whether a command acts on a range of lines, and which lines it acts
+/// on when no range is given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ranged {
+    /// The command doesn't take a range.
+    No,
+    /// Without a range, the command acts on the selection.
+    OrSelection,
+    /// Without a range, the command acts on the line of the cursor.
+    OrCurrentLine,
+}
+
+/// Description of a command: its names, its arguments, and how to
+/// build it once the arguments have been parsed.
+pub struct CommandSpec {
+    /// Long name of the command.
+    pub name: &'static str,
+    /// Short name of the command, if any.
+    pub short: Option<&'static str>,
+    /// Arguments of the command. Optional arguments come last.
+    pub args: &'static [ArgSpec],
+    /// What the command does, for the help and the documentation.
+    pub description: &'static str,
+    /// Whether the command can be prefixed by a range of lines.
+    pub range: Ranged,
+    /// Build the command from its arguments, which have already been
+    /// checked against `args`.
+    build: fn(&mut Args) -> Option<Command>,
+}
+
+/// The parsed arguments of a command, in order.
+pub struct Args(::std::vec::IntoIter<Arg>);
+
+enum Arg {
+    Text(String),
+    Setting(Setting),
+    PluginAction(PluginAction),
+    Position(u64, Option<u64>),
+    Count(usize),
+}
+
+impl Args {
+    fn text(&mut self) -> Option<String> {
+        match self.0.next() {
+            Some(Arg::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn position(&mut self) -> Option<(u64, Option<u64>)> {
+        match self.0.next() {
+            Some(Arg::Position(line, column)) => Some((line, column)),
+            _ => None,
+        }
+    }
+
+    fn setting(&mut self) -> Option<Setting> {
+        match self.0.next() {
+            Some(Arg::Setting(setting)) => Some(setting),
+            _ => None,
+        }
+    }
+
+    fn plugin_action(&mut self) -> Option<PluginAction> {
+        match self.0.next() {
+            Some(Arg::PluginAction(action)) => Some(action),
+            _ => None,
+        }
+    }
+
+    fn count(&mut self) -> Option<usize> {
+        match self.0.next() {
+            Some(Arg::Count(count)) => Some(count),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! arg {
+    ($name:expr, $kind:ident) => {
+        ArgSpec {
+            name: $name,
+            kind: ArgKind::$kind,
+            optional: false,
+        }
+    };
+    ($name:expr, $kind:ident, optional) => {
+        ArgSpec {
+            name: $name,
+            kind: ArgKind::$kind,
+            optional: true,
+        }
+    };
+}
+
+/// Declare a command that takes no argument.
+macro_rules! simple {
+    ($name:expr, $short:expr, $cmd:expr, $description:expr) => {
+        CommandSpec {
+            name: $name,
+            short: $short,
+            args: &[],
+            description: $description,
+            range: Ranged::No,
+            build: |_| Some($cmd),
+        }
+    };
+}
+
+/// Declare a command that takes no argument, but a range of lines.
+macro_rules! ranged {
+    ($name:expr, $short:expr, $cmd:expr, $range:ident, $description:expr) => {
+        CommandSpec {
+            name: $name,
+            short: $short,
+            args: &[],
+            description: $description,
+            range: Ranged::$range,
+            build: |_| Some($cmd),
+        }
+    };
+}
+
+/// All the commands that can be run from the command prompt.
+pub static COMMANDS: &[CommandSpec] = &[
+    simple!(
+        "quit",
+        Some("q"),
+        Command::Quit,
+        "Quit xi-term, unless some buffers have unsaved changes"
+    ),
+    simple!(
+        "force-quit",
+        Some("q!"),
+        Command::ForceQuit,
+        "Quit xi-term, discarding the unsaved changes"
+    ),
+    CommandSpec {
+        name: "save",
+        short: Some("s"),
+        args: &[arg!("file", File, optional)],
+        description: "Save the current file, or save it as `file`",
+        range: Ranged::No,
+        build: |args| Some(Command::Save(None, args.text())),
+    },
+    simple!(
+        "save-all",
+        Some("wa"),
+        Command::SaveAll,
+        "Save all the buffers that have a file name"
+    ),
+    CommandSpec {
+        name: "write-quit",
+        short: Some("wq"),
+        args: &[arg!("file", File, optional)],
+        description: "Save the current file, or save it as `file`, and quit",
+        range: Ranged::No,
+        build: |args| Some(Command::WriteQuit(args.text())),
+    },
+    CommandSpec {
+        name: "open",
+        short: Some("o"),
+        args: &[arg!("file", File, optional)],
+        description: "Open `file` for editing, or a new empty buffer",
+        range: Ranged::No,
+        build: |args| Some(Command::Open(args.text())),
+    },
+    simple!(
+        "back",
+        Some("b"),
+        Command::Back,
+        "Delete the previous character and move the cursor one position back"
+    ),
+    simple!(
+        "delete",
+        Some("d"),
+        Command::Delete,
+        "Delete the character under the cursor"
+    ),
+    simple!(
+        "next-buffer",
+        Some("bn"),
+        Command::NextBuffer,
+        "Switch to the next buffer"
+    ),
+    simple!(
+        "prev-buffer",
+        Some("bp"),
+        Command::PrevBuffer,
+        "Switch to the previous buffer"
+    ),
+    CommandSpec {
+        name: "buffer",
+        short: Some("bs"),
+        args: &[arg!("name", Buffer)],
+        description: "Switch to the buffer `name`",
+        range: Ranged::No,
+        build: |args| Some(Command::SwitchBuffer(args.text()?)),
+    },
+    simple!(
+        "buffers",
+        Some("ls"),
+        Command::ListBuffers,
+        "List the open buffers, marking the current one with `%` and the modified ones with `[+]`"
+    ),
+    simple!(
+        "close",
+        Some("c"),
+        Command::Close(None, false),
+        "Close the current buffer, unless it has unsaved changes"
+    ),
+    simple!(
+        "force-close",
+        Some("c!"),
+        Command::Close(None, true),
+        "Close the current buffer, discarding its unsaved changes"
+    ),
+    CommandSpec {
+        name: "buffer-delete",
+        short: Some("bd"),
+        args: &[arg!("n", Count, optional)],
+        description: "Close the buffer number `n` (as listed by `buffers`), or the current one, \
+                      unless it has unsaved changes",
+        range: Ranged::No,
+        build: |args| Some(Command::Close(args.count(), false)),
+    },
+    CommandSpec {
+        name: "force-buffer-delete",
+        short: Some("bd!"),
+        args: &[arg!("n", Count, optional)],
+        description: "Close the buffer number `n`, or the current one, \
+                      discarding its unsaved changes",
+        range: Ranged::No,
+        build: |args| Some(Command::Close(args.count(), true)),
+    },
+    simple!(
+        "page-down",
+        Some("pd"),
+        Command::PageDown,
+        "Advance the current view by one page"
+    ),
+    simple!(
+        "page-up",
+        Some("pu"),
+        Command::PageUp,
+        "Move the current view back by one page"
+    ),
+    simple!(
+        "move-left",
+        Some("ml"),
+        Command::MoveLeft,
+        "Move the cursor one position left"
+    ),
+    simple!(
+        "move-right",
+        Some("mr"),
+        Command::MoveRight,
+        "Move the cursor one position right"
+    ),
+    simple!(
+        "move-up",
+        Some("mu"),
+        Command::MoveUp,
+        "Move the cursor one line up"
+    ),
+    simple!(
+        "move-down",
+        Some("md"),
+        Command::MoveDown,
+        "Move the cursor one line down"
+    ),
+    simple!(
+        "move-word-left",
+        Some("mwl"),
+        Command::MoveWordLeft,
+        "Move the cursor one word left"
+    ),
+    simple!(
+        "move-word-right",
+        Some("mwr"),
+        Command::MoveWordRight,
+        "Move the cursor one word right"
+    ),
+    simple!(
+        "move-paragraph-up",
+        Some("mpu"),
+        Command::ParagraphUp,
+        "Move the cursor to the start of the paragraph"
+    ),
+    simple!(
+        "move-paragraph-down",
+        Some("mpd"),
+        Command::ParagraphDown,
+        "Move the cursor to the end of the paragraph"
+    ),
+    simple!(
+        "move-document-start",
+        Some("mds"),
+        Command::DocumentStart,
+        "Move the cursor to the start of the document"
+    ),
+    simple!(
+        "move-document-end",
+        Some("mde"),
+        Command::DocumentEnd,
+        "Move the cursor to the end of the document"
+    ),
+    CommandSpec {
+        name: "goto",
+        short: Some("g"),
+        args: &[arg!("line[:column]", Position)],
+        description: "Move the cursor to `line`, and optionally `column`. \
+                      A line number alone, e.g. `42`, works too",
+        range: Ranged::No,
+        build: |args| {
+            let (line, column) = args.position()?;
+            Some(Command::Goto(line, column))
+        },
+    },
+    simple!(
+        "select-left",
+        Some("sl"),
+        Command::SelectLeft,
+        "Move the cursor one position left and update the current selection accordingly"
+    ),
+    simple!(
+        "select-right",
+        Some("sr"),
+        Command::SelectRight,
+        "Move the cursor one position right and update the current selection accordingly"
+    ),
+    simple!(
+        "select-up",
+        Some("su"),
+        Command::SelectUp,
+        "Move the cursor one line up and update the current selection accordingly"
+    ),
+    simple!(
+        "select-down",
+        Some("sd"),
+        Command::SelectDown,
+        "Move the cursor one line down and update the current selection accordingly"
+    ),
+    simple!(
+        "select-word-left",
+        Some("swl"),
+        Command::SelectWordLeft,
+        "Move the cursor one word left and update the current selection accordingly"
+    ),
+    simple!(
+        "select-word-right",
+        Some("swr"),
+        Command::SelectWordRight,
+        "Move the cursor one word right and update the current selection accordingly"
+    ),
+    simple!(
+        "select-home",
+        Some("sh"),
+        Command::SelectHome,
+        "Move the cursor to the start of the line and update the current selection accordingly"
+    ),
+    simple!(
+        "select-end",
+        Some("se"),
+        Command::SelectEnd,
+        "Move the cursor to the end of the line and update the current selection accordingly"
+    ),
+    simple!(
+        "select-page-up",
+        Some("spu"),
+        Command::SelectPageUp,
+        "Move the cursor one page up and update the current selection accordingly"
+    ),
+    simple!(
+        "select-page-down",
+        Some("spd"),
+        Command::SelectPageDown,
+        "Move the cursor one page down and update the current selection accordingly"
+    ),
+    simple!(
+        "select-document-start",
+        Some("sds"),
+        Command::SelectDocumentStart,
+        "Move the cursor to the start of the document and update the current selection accordingly"
+    ),
+    simple!(
+        "select-document-end",
+        Some("sde"),
+        Command::SelectDocumentEnd,
+        "Move the cursor to the end of the document and update the current selection accordingly"
+    ),
+    simple!(
+        "add-cursor-above",
+        Some("aca"),
+        Command::AddCursorAbove,
+        "Add a cursor on the line above"
+    ),
+    simple!(
+        "add-cursor-below",
+        Some("acb"),
+        Command::AddCursorBelow,
+        "Add a cursor on the line below"
+    ),
+    simple!(
+        "select-next-occurrence",
+        Some("sno"),
+        Command::SelectNextOccurrence,
+        "Add the next occurrence of the current word or selection to the selections"
+    ),
+    simple!(
+        "select-all-occurrences",
+        Some("sao"),
+        Command::SelectAllOccurrences,
+        "Select all the occurrences of the current word or selection"
+    ),
+    simple!(
+        "collapse-selections",
+        Some("cs"),
+        Command::CollapseSelections,
+        "Go back to a single cursor"
+    ),
+    CommandSpec {
+        name: "theme",
+        short: Some("t"),
+        args: &[arg!("theme", Theme)],
+        description: "Set the theme to `theme`",
+        range: Ranged::No,
+        build: |args| Some(Command::SetTheme(args.text()?)),
+    },
+    CommandSpec {
+        name: "language",
+        short: Some("lang"),
+        args: &[arg!("language", Language, optional)],
+        description: "Set the language of the current buffer to `language`, or show it",
+        range: Ranged::No,
+        build: |args| Some(Command::SetLanguage(args.text())),
+    },
+    simple!(
+        "line-numbers",
+        Some("ln"),
+        Command::ToggleLineNumbers,
+        "Toggle displaying line numbers"
+    ),
+    simple!(
+        "copy",
+        Some("y"),
+        Command::Copy,
+        "Copy the selection to the clipboard"
+    ),
+    simple!(
+        "cut",
+        Some("x"),
+        Command::Cut,
+        "Cut the selection to the clipboard"
+    ),
+    simple!(
+        "paste",
+        Some("p"),
+        Command::Paste,
+        "Paste the clipboard content"
+    ),
+    simple!("undo", Some("u"), Command::Undo, "Undo the last edit"),
+    simple!(
+        "redo",
+        Some("r"),
+        Command::Redo,
+        "Redo the last undone edit"
+    ),
+    CommandSpec {
+        name: "record",
+        short: Some("rec"),
+        args: &[arg!("register", Register)],
+        description: "Start recording a macro in `register`",
+        range: Ranged::No,
+        build: |args| Some(Command::Record(args.text()?)),
+    },
+    simple!(
+        "stop",
+        None,
+        Command::StopRecording,
+        "Stop recording the current macro"
+    ),
+    CommandSpec {
+        name: "play",
+        short: None,
+        args: &[arg!("register", Register), arg!("count", Count, optional)],
+        description: "Play the macro in `register`, `count` times",
+        range: Ranged::No,
+        build: |args| {
+            let register = args.text()?;
+            Some(Command::Play(register, args.count().unwrap_or(1)))
+        },
+    },
+    ranged!(
+        "delete-lines",
+        Some("dl"),
+        Command::DeleteLines,
+        OrCurrentLine,
+        "Delete the lines of the range, or the current line"
+    ),
+    ranged!(
+        "sort",
+        None,
+        Command::Sort,
+        OrSelection,
+        "Sort the lines of the range, or of the selection"
+    ),
+    ranged!(
+        "indent",
+        None,
+        Command::Indent,
+        OrSelection,
+        "Indent the lines of the range, or of the selection"
+    ),
+    ranged!(
+        "outdent",
+        None,
+        Command::Outdent,
+        OrSelection,
+        "Outdent the lines of the range, or of the selection"
+    ),
+    CommandSpec {
+        name: "search",
+        short: Some("?"),
+        args: &[arg!("text", Text), arg!("flags", SearchFlags, optional)],
+        description: "Search for `text`, and highlight its matches. `flags` are letters among \
+                      `c` (case sensitive), `w` (whole words) and `r` (regex)",
+        range: Ranged::No,
+        build: |args| {
+            let text = args.text()?;
+            let flags = args.text().unwrap_or_default();
+            Some(Command::Search(SearchQuery::new(text, &flags)))
+        },
+    },
+    simple!(
+        "search-next",
+        Some("sn"),
+        Command::SearchNext,
+        "Move to the next match of the search"
+    ),
+    simple!(
+        "search-prev",
+        Some("sp"),
+        Command::SearchPrev,
+        "Move to the previous match of the search"
+    ),
+    CommandSpec {
+        name: "replace",
+        short: Some("rep"),
+        args: &[
+            arg!("pattern", Text),
+            arg!("replacement", Text),
+            arg!("flags", SearchFlags, optional),
+        ],
+        description: "Replace the matches of `pattern` with `replacement`, asking for each of \
+                      them: y replaces it, n skips it, a replaces all the matches and q stops. \
+                      `flags` are the same as for `search`",
+        range: Ranged::No,
+        build: |args| {
+            let pattern = args.text()?;
+            let replacement = args.text()?;
+            let flags = args.text().unwrap_or_default();
+            Some(Command::Replace(
+                SearchQuery::new(pattern, &flags),
+                replacement,
+                false,
+            ))
+        },
+    },
+    CommandSpec {
+        name: "replace-all",
+        short: Some("repa"),
+        args: &[
+            arg!("pattern", Text),
+            arg!("replacement", Text),
+            arg!("flags", SearchFlags, optional),
+        ],
+        description: "Replace all the matches of `pattern` with `replacement`",
+        range: Ranged::No,
+        build: |args| {
+            let pattern = args.text()?;
+            let replacement = args.text()?;
+            let flags = args.text().unwrap_or_default();
+            Some(Command::Replace(
+                SearchQuery::new(pattern, &flags),
+                replacement,
+                true,
+            ))
+        },
+    },
+    simple!(
+        "no-highlight",
+        Some("noh"),
+        Command::NoHighlight,
+        "Stop highlighting the matches of the search, until the next one"
+    ),
+    CommandSpec {
+        name: "set",
+        short: None,
+        args: &[arg!("option=value", Setting)],
+        description: "Change an option of the current buffer, or show its value with \
+                      `set option?`",
+        range: Ranged::No,
+        build: |args| Some(Command::Set(args.setting()?, false)),
+    },
+    CommandSpec {
+        name: "set-global",
+        short: Some("setg"),
+        args: &[arg!("option=value", Setting)],
+        description: "Change an option of all the buffers, including the ones opened later",
+        range: Ranged::No,
+        build: |args| Some(Command::Set(args.setting()?, true)),
+    },
+    simple!(
+        "plugins",
+        None,
+        Command::ListPlugins,
+        "List the plugins of the current buffer, and whether they are running"
+    ),
+    CommandSpec {
+        name: "plugin",
+        short: None,
+        args: &[arg!("action", PluginAction), arg!("name", Plugin)],
+        description: "Start or stop the plugin `name` for the current buffer, \
+                      depending on whether `action` is `start` or `stop`",
+        range: Ranged::No,
+        build: |args| Some(Command::Plugin(args.plugin_action()?, args.text()?)),
+    },
+    CommandSpec {
+        name: "help",
+        short: Some("h"),
+        args: &[arg!("command", Command, optional)],
+        description: "Describe `command`, or list the commands",
+        range: Ranged::No,
+        build: |args| Some(Command::Help(args.text())),
+    },
+];
+
+impl CommandSpec {
+    /// Find a command by its long or short name.
+    pub fn find(name: &str) -> Option<&'static CommandSpec> {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.name == name || spec.short == Some(name))
+    }
+
+    /// Describe how the command is used, e.g. `play <register> [count]`.
+    pub fn usage(&self, name: &str) -> String {
+        let mut usage = match self.range {
+            Ranged::No => name.to_string(),
+            _ => format!("[range] {}", name),
+        };
+        for arg in self.args {
+            if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+
+    /// Check the arguments given to the command, and build it.
+    fn parse(&self, cmd: &str, values: &[Word]) -> Result<Command, ParseCommandError> {
+        if values.len() > self.args.len() {
+            return Err(ParseCommandError::TooManyArguments {
+                cmd: cmd.into(),
+                expected: self.args.len(),
+                found: values.len(),
+            });
+        }
+        let mut args = Vec::new();
+        for (i, spec) in self.args.iter().enumerate() {
+            match values.get(i) {
+                Some(value) => args.push(spec.parse(cmd, value)?),
+                None if spec.optional => break,
+                None => {
+                    return Err(ParseCommandError::ExpectedArgument {
+                        cmd: cmd.into(),
+                        arg: spec.name,
+                    })
+                }
+            }
+        }
+        (self.build)(&mut Args(args.into_iter())).ok_or_else(|| {
+            ParseCommandError::ExpectedArgument {
+                cmd: cmd.into(),
+                arg: self.args.first().map(|arg| arg.name).unwrap_or(""),
+            }
+        })
+    }
 }
 
+impl ArgSpec {
+    fn parse(&self, cmd: &str, word: &Word) -> Result<Arg, ParseCommandError> {
+        let value = word.text();
+        let invalid = |reason: &str| ParseCommandError::InvalidArgument {
+            cmd: cmd.into(),
+            arg: self.name,
+            value: word.text(),
+            reason: reason.into(),
+        };
+        match self.kind {
+            ArgKind::File => match word.expand() {
+                Ok(path) => Ok(Arg::Text(path)),
+                Err(_) => Err(invalid("undefined environment variable")),
+            },
+            ArgKind::Theme
+            | ArgKind::Register
+            | ArgKind::Buffer
+            | ArgKind::Plugin
+            | ArgKind::Language
+            | ArgKind::Text => Ok(Arg::Text(value)),
+            ArgKind::Command => match CommandSpec::find(&value) {
+                Some(_) => Ok(Arg::Text(value)),
+                None => Err(invalid("no such command")),
+            },
+            ArgKind::Position => match parse_position(&value) {
+                Some((line, column)) => Ok(Arg::Position(line, column)),
+                None => Err(invalid("expected <line>[:<column>], starting at 1")),
+            },
+            ArgKind::SearchFlags if SearchQuery::valid_flags(&value) => Ok(Arg::Text(value)),
+            ArgKind::SearchFlags => Err(invalid("expected letters among c, w and r")),
+            ArgKind::Setting => match value.parse::<Setting>() {
+                Ok(setting) => Ok(Arg::Setting(setting)),
+                Err(reason) => Err(ParseCommandError::InvalidSetting {
+                    setting: value,
+                    reason,
+                }),
+            },
+            ArgKind::PluginAction => match value.as_str() {
+                "start" => Ok(Arg::PluginAction(PluginAction::Start)),
+                "stop" => Ok(Arg::PluginAction(PluginAction::Stop)),
+                _ => Err(invalid("expected start or stop")),
+            },
+            ArgKind::Count => match value.parse::<usize>() {
+                Ok(count) if count > 0 && count <= MAX_COUNT => Ok(Arg::Count(count)),
+                _ => Err(invalid(&format!(
+                    "expected a number from 1 to {}",
+                    MAX_COUNT
+                ))),
+            },
+        }
+    }
+}
+
+/// Get the help for a command, or the list of commands.
+pub fn help(name: Option<&str>) -> String {
+    match name.and_then(CommandSpec::find) {
+        Some(spec) => {
+            let mut help = spec.usage(spec.name);
+            if let Some(short) = spec.short {
+                help.push_str(&format!(" ({})", short));
+            }
+            format!("{}: {}", help, spec.description)
+        }
+        None => {
+            let names: Vec<&str> = COMMANDS.iter().map(|spec| spec.name).collect();
+            format!("help <command> for details. Commands: {}", names.join(", "))
+        }
+    }
+}
+
+/// Generate the Markdown table of the commands found in the README.
+pub fn commands_table() -> String {
+    let mut table = String::from(
+        "| Short form | Long form | Description |\n\
+         | ---------- | --------- | ----------- |\n",
+    );
+    for spec in COMMANDS {
+        let args: String = spec
+            .args
+            .iter()
+            .map(|arg| {
+                if arg.optional {
+                    format!(" `[{}]`", arg.name)
+                } else {
+                    format!(" `{}`", arg.name)
+                }
+            })
+            .collect();
+        let range = match spec.range {
+            Ranged::No => "",
+            _ => "`[range]` ",
+        };
+        let short = spec
+            .short
+            .map(|short| format!("{}{}{}", range, short, args))
+            .unwrap_or_default();
+        table.push_str(&format!(
+            "| {} | {}{}{} | {} |\n",
+            short, range, spec.name, args, spec.description
+        ));
+    }
+    table
+}
+
+impl Command {
+    /// Parse a command that may also be one of the commands provided by
+    /// the plugins, i.e. a name for which `is_plugin_command` is true.
+    /// Other names that are not registered are unknown commands.
+    pub fn parse<F>(s: &str, is_plugin_command: F) -> Result<Command, ParseCommandError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        // Several commands can be separated by ";", e.g. "s; bn".
+        let mut commands = split_commands(s)
+            .into_iter()
+            .filter(|cmd| !cmd.trim().is_empty())
+            .map(|cmd| parse_command(cmd, &is_plugin_command))
+            .collect::<Result<Vec<Command>, _>>()?;
+        match commands.len() {
+            0 => Err(ParseCommandError::UnknownCommand(String::new())),
+            1 => Ok(commands.remove(0)),
+            _ => Ok(Command::Sequence(commands)),
+        }
+    }
+}
+
+/// Parse a command without plugin commands: only the registered
+/// commands are known.
 impl FromStr for Command {
     type Err = ParseCommandError;
 
     fn from_str(s: &str) -> Result<Command, Self::Err> {
-        match &s[..] {
-            "s" | "save" => Ok(Command::Save(None)),
-            "q" | "quit" => Ok(Command::Quit),
-            "b" | "back" => Ok(Command::Back),
-            "d" | "delete" => Ok(Command::Delete),
-            "bn" | "next-buffer" => Ok(Command::NextBuffer),
-            "bp" | "prev-buffer" => Ok(Command::PrevBuffer),
-            "pd" | "page-down" => Ok(Command::PageDown),
-            "pu" | "page-up" => Ok(Command::PageUp),
-            "ml" | "move-left" => Ok(Command::MoveLeft),
-            "mr" | "move-right" => Ok(Command::MoveRight),
-            "mu" | "move-up" => Ok(Command::MoveUp),
-            "md" | "move-down" => Ok(Command::MoveDown),
-            "ln" | "line-numbers" => Ok(Command::ToggleLineNumbers),
-            command => {
-                let mut parts: Vec<&str> = command.split(' ').collect();
-
-                let cmd = parts.remove(0);
-                match cmd {
-                    "t" | "theme" => {
-                        if parts.is_empty() {
-                            Err(ParseCommandError::ExpectedArgument {
-                                cmd: "theme".into(),
-                                expected: 1,
-                                found: 0,
-                            })
-                        } else if parts.len() > 1 {
-                            Err(ParseCommandError::TooManyArguments {
-                                cmd: cmd.to_owned(),
-                                expected: 1,
-                                found: parts.len(),
-                            })
-                        } else {
-                            Ok(Command::SetTheme(parts[0].to_owned()))
-                        }
-                    }
-                    "o" | "open" => {
-                        if parts.is_empty() {
-                            Ok(Command::Open(None))
-                        } else if parts.len() > 1 {
-                            Err(ParseCommandError::UnexpectedArgument)
-                        } else {
-                            Ok(Command::Open(Some(parts[0].to_owned())))
-                        }
+        Command::parse(s, |_| false)
+    }
+}
+
+/// Parse a single command, which may be prefixed by a count or a range.
+fn parse_command<F>(s: &str, is_plugin_command: &F) -> Result<Command, ParseCommandError>
+where
+    F: Fn(&str) -> bool,
+{
+    let s = s.trim();
+
+    // A number alone is a line to go to, e.g. "42".
+    let count_len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    if count_len > 0 && count_len == s.len() {
+        return CommandSpec::find("goto")
+            .expect("goto is a registered command")
+            .parse("goto", &[Word::literal(s)]);
+    }
+
+    // A command can be prefixed by a range of lines, e.g. "10,20 sort".
+    // A line number alone is a range for the commands that take one,
+    // e.g. "10 dl", and a count for the others.
+    let range_len = Range::prefix_len(s);
+    let line_range = count_len > 0 && count_len == range_len && takes_range(&s[count_len..]);
+    if line_range || Range::is_range(&s[..range_len]) {
+        let invalid = |reason| ParseCommandError::InvalidRange {
+            range: s[..range_len].into(),
+            reason,
+        };
+        let range = s[..range_len].parse::<Range>().map_err(invalid)?;
+        if s[range_len..].trim().is_empty() {
+            return Err(invalid("missing command"));
+        }
+        let (name, cmd) = parse_named(&s[range_len..], is_plugin_command)?;
+        return match CommandSpec::find(&name).map(|spec| spec.range) {
+            Some(Ranged::No) | None => Err(ParseCommandError::UnexpectedRange(name)),
+            Some(_) => Ok(Command::Ranged(range, Box::new(cmd))),
+        };
+    }
+
+    // A command can be prefixed by a count, e.g. "5md".
+    if count_len > 0 {
+        let count = match s[..count_len].parse::<usize>() {
+            Ok(count) if count > 0 && count <= MAX_COUNT => count,
+            _ => return Err(ParseCommandError::InvalidCount(s[..count_len].into())),
+        };
+        let cmd = parse_command(&s[count_len..], is_plugin_command)?;
+        return Ok(Command::Repeat(count, Box::new(cmd)));
+    }
+
+    let (name, cmd) = parse_named(s, is_plugin_command)?;
+    match CommandSpec::find(&name).map(|spec| spec.range) {
+        Some(Ranged::OrCurrentLine) => Ok(Command::Ranged(Range::CURRENT, Box::new(cmd))),
+        _ => Ok(cmd),
+    }
+}
+
+/// Whether a command, given with its arguments, can be prefixed by a
+/// range.
+fn takes_range(s: &str) -> bool {
+    let name = match tokenize(s) {
+        Ok(ref words) if !words.is_empty() => words[0].text(),
+        _ => return false,
+    };
+    CommandSpec::find(&name).is_some_and(|spec| spec.range != Ranged::No)
+}
+
+/// Parse a command name and its arguments. Return the name as it was
+/// given, for the error messages.
+fn parse_named<F>(s: &str, is_plugin_command: &F) -> Result<(String, Command), ParseCommandError>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut words = tokenize(s)?;
+    if words.is_empty() {
+        return Err(ParseCommandError::UnknownCommand(String::new()));
+    }
+    let name = words.remove(0).text();
+    match CommandSpec::find(&name) {
+        Some(spec) => Ok((name.clone(), spec.parse(&name, &words)?)),
+        // Plugin commands don't take arguments.
+        None if words.is_empty() && is_plugin_command(&name) => {
+            Ok((name.clone(), Command::PluginCmd(name)))
+        }
+        None => Err(ParseCommandError::UnknownCommand(name)),
+    }
+}
+
+/// Format a command so that it can be parsed back, using the long form
+/// of its name. This is how commands are saved in macros.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::Cancel => write!(f, "cancel"),
+            Command::Save(_, None) => write!(f, "save"),
+            Command::Save(_, Some(ref file)) => write!(f, "save {}", quote(file)),
+            Command::SaveAll => write!(f, "save-all"),
+            Command::WriteQuit(None) => write!(f, "write-quit"),
+            Command::WriteQuit(Some(ref file)) => write!(f, "write-quit {}", quote(file)),
+            Command::ForceQuit => write!(f, "force-quit"),
+            Command::Open(None) => write!(f, "open"),
+            Command::Open(Some(ref file)) => write!(f, "open {}", quote(file)),
+            Command::SetTheme(ref theme) => write!(f, "theme {}", quote(theme)),
+            Command::SetLanguage(None) => write!(f, "language"),
+            Command::SetLanguage(Some(ref language)) => {
+                write!(f, "language {}", quote(language))
+            }
+            Command::Goto(line, None) => write!(f, "goto {}", line),
+            Command::Goto(line, Some(column)) => write!(f, "goto {}:{}", line, column),
+            Command::Repeat(count, ref cmd) => write!(f, "{} {}", count, cmd),
+            Command::Record(ref register) => write!(f, "record {}", quote(register)),
+            Command::Play(ref register, count) => {
+                write!(f, "play {} {}", quote(register), count)
+            }
+            Command::StopRecording => write!(f, "stop"),
+            Command::Help(None) => write!(f, "help"),
+            Command::Help(Some(ref cmd)) => write!(f, "help {}", cmd),
+            Command::Set(ref setting, false) => write!(f, "set {}", setting),
+            Command::Set(ref setting, true) => write!(f, "set-global {}", setting),
+            Command::ListPlugins => write!(f, "plugins"),
+            Command::Plugin(action, ref name) => write!(f, "plugin {} {}", action, quote(name)),
+            Command::PluginCmd(ref name) => write!(f, "{}", quote(name)),
+            Command::Search(ref query) => {
+                write!(f, "search {}", quote(&query.text))?;
+                let flags = query.flags();
+                if !flags.is_empty() {
+                    write!(f, " {}", flags)?;
+                }
+                Ok(())
+            }
+            Command::SearchNext => write!(f, "search-next"),
+            Command::SearchPrev => write!(f, "search-prev"),
+            Command::NoHighlight => write!(f, "no-highlight"),
+            Command::Replace(ref query, ref replacement, all) => {
+                write!(
+                    f,
+                    "{} {} {}",
+                    if all { "replace-all" } else { "replace" },
+                    quote(&query.text),
+                    quote(replacement)
+                )?;
+                let flags = query.flags();
+                if !flags.is_empty() {
+                    write!(f, " {}", flags)?;
+                }
+                Ok(())
+            }
+            Command::Quit => write!(f, "quit"),
+            Command::Back => write!(f, "back"),
+            Command::Delete => write!(f, "delete"),
+            Command::NextBuffer => write!(f, "next-buffer"),
+            Command::PrevBuffer => write!(f, "prev-buffer"),
+            Command::SwitchBuffer(ref name) => write!(f, "buffer {}", quote(name)),
+            Command::ListBuffers => write!(f, "buffers"),
+            Command::Close(None, false) => write!(f, "close"),
+            Command::Close(None, true) => write!(f, "force-close"),
+            Command::Close(Some(n), false) => write!(f, "buffer-delete {}", n),
+            Command::Close(Some(n), true) => write!(f, "force-buffer-delete {}", n),
+            Command::PageDown => write!(f, "page-down"),
+            Command::PageUp => write!(f, "page-up"),
+            Command::MoveLeft => write!(f, "move-left"),
+            Command::MoveRight => write!(f, "move-right"),
+            Command::MoveUp => write!(f, "move-up"),
+            Command::MoveDown => write!(f, "move-down"),
+            Command::MoveWordLeft => write!(f, "move-word-left"),
+            Command::MoveWordRight => write!(f, "move-word-right"),
+            Command::ParagraphUp => write!(f, "move-paragraph-up"),
+            Command::ParagraphDown => write!(f, "move-paragraph-down"),
+            Command::DocumentStart => write!(f, "move-document-start"),
+            Command::DocumentEnd => write!(f, "move-document-end"),
+            Command::SelectLeft => write!(f, "select-left"),
+            Command::SelectRight => write!(f, "select-right"),
+            Command::SelectUp => write!(f, "select-up"),
+            Command::SelectDown => write!(f, "select-down"),
+            Command::SelectWordLeft => write!(f, "select-word-left"),
+            Command::SelectWordRight => write!(f, "select-word-right"),
+            Command::SelectHome => write!(f, "select-home"),
+            Command::SelectEnd => write!(f, "select-end"),
+            Command::SelectPageUp => write!(f, "select-page-up"),
+            Command::SelectPageDown => write!(f, "select-page-down"),
+            Command::SelectDocumentStart => write!(f, "select-document-start"),
+            Command::SelectDocumentEnd => write!(f, "select-document-end"),
+            Command::AddCursorAbove => write!(f, "add-cursor-above"),
+            Command::AddCursorBelow => write!(f, "add-cursor-below"),
+            Command::SelectNextOccurrence => write!(f, "select-next-occurrence"),
+            Command::SelectAllOccurrences => write!(f, "select-all-occurrences"),
+            Command::CollapseSelections => write!(f, "collapse-selections"),
+            Command::ToggleLineNumbers => write!(f, "line-numbers"),
+            Command::Copy => write!(f, "copy"),
+            Command::Cut => write!(f, "cut"),
+            Command::Paste => write!(f, "paste"),
+            Command::Undo => write!(f, "undo"),
+            Command::Redo => write!(f, "redo"),
+            Command::DeleteLines => write!(f, "delete-lines"),
+            Command::Sort => write!(f, "sort"),
+            Command::Indent => write!(f, "indent"),
+            Command::Outdent => write!(f, "outdent"),
+            Command::Ranged(ref range, ref cmd) => write!(f, "{} {}", range, cmd),
+            Command::Sequence(ref cmds) => {
+                let cmds: Vec<String> = cmds.iter().map(Command::to_string).collect();
+                write!(f, "{}", cmds.join("; "))
+            }
+        }
+    }
+}
+
+/// Parse a `<line>[:<column>]` position, where both numbers start at 1.
+fn parse_position(arg: &str) -> Option<(u64, Option<u64>)> {
+    let mut parts = arg.splitn(2, ':');
+    let line = match parts.next().map(str::parse::<u64>) {
+        Some(Ok(line)) if line > 0 => line,
+        _ => return None,
+    };
+    let column = match parts.next().map(str::parse::<u64>) {
+        Some(Ok(column)) if column > 0 => Some(column),
+        Some(_) => return None,
+        None => None,
+    };
+    Some((line, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A valid value for an argument of the given kind.
+    fn sample(kind: ArgKind) -> &'static str {
+        match kind {
+            ArgKind::File => "'my notes.txt'",
+            ArgKind::Theme => "base16-eighties.dark",
+            ArgKind::Position => "12:3",
+            ArgKind::Register => "q",
+            ArgKind::Count => "3",
+            ArgKind::Command => "quit",
+            ArgKind::Buffer => "main.rs",
+            ArgKind::Text => "'fn main'",
+            ArgKind::SearchFlags => "cw",
+            ArgKind::Setting => "tab_size=2",
+            ArgKind::PluginAction => "start",
+            ArgKind::Plugin => "syntect",
+            ArgKind::Language => "'Plain Text'",
+        }
+    }
+
+    fn parse(input: &str) -> Command {
+        match input.parse::<Command>() {
+            Ok(cmd) => cmd,
+            Err(e) => panic!("cannot parse {:?}: {}", input, e),
+        }
+    }
+
+    /// Check that a command is printed as something that parses back
+    /// into the same command.
+    fn assert_round_trip(input: &str) {
+        let cmd = parse(input);
+        let printed = cmd.to_string();
+        let reparsed = parse(&printed);
+        assert_eq!(
+            format!("{:?}", cmd),
+            format!("{:?}", reparsed),
+            "{:?} was printed as {:?}",
+            input,
+            printed
+        );
+        assert_eq!(printed, reparsed.to_string());
+    }
+
+    #[test]
+    fn every_command_round_trips() {
+        for spec in COMMANDS {
+            let args: Vec<&str> = spec.args.iter().map(|arg| sample(arg.kind)).collect();
+            for name in Some(spec.name).into_iter().chain(spec.short) {
+                // Without the optional arguments, and with all of them.
+                let required = spec.args.iter().filter(|arg| !arg.optional).count();
+                assert_round_trip(&format!("{} {}", name, args[..required].join(" ")));
+                assert_round_trip(&format!("{} {}", name, args.join(" ")));
+            }
+        }
+    }
+
+    #[test]
+    fn prefixed_commands_round_trip() {
+        for input in &[
+            "5md",
+            "3 play q 2",
+            "10,20 delete-lines",
+            "% sort",
+            ".,$ indent",
+            "dl",
+            "10 dl",
+            ".+1,$-1 sort",
+            "-2,+2 indent",
+            "3 . dl",
+            "s; bn; 2 mu",
+            "search 'a;b' r",
+            "replace-all \"it's\" 'it is'",
+            "42",
+        ] {
+            assert_round_trip(input);
+        }
+    }
+
+    #[test]
+    fn missing_arguments() {
+        for spec in COMMANDS {
+            if spec.args.iter().any(|arg| !arg.optional) {
+                match spec.name.parse::<Command>() {
+                    Err(ParseCommandError::ExpectedArgument { ref cmd, arg }) => {
+                        assert_eq!(cmd, spec.name);
+                        assert_eq!(arg, spec.args[0].name);
                     }
-                    _ => Err(ParseCommandError::UnknownCommand(command.into())),
+                    other => panic!(
+                        "{}: expected a missing argument, got {:?}",
+                        spec.name, other
+                    ),
                 }
             }
         }
     }
+
+    #[test]
+    fn too_many_arguments() {
+        for spec in COMMANDS {
+            let mut args: Vec<&str> = spec.args.iter().map(|arg| sample(arg.kind)).collect();
+            args.push("extra");
+            let input = format!("{} {}", spec.name, args.join(" "));
+            match input.parse::<Command>() {
+                Err(ParseCommandError::TooManyArguments {
+                    ref cmd,
+                    expected,
+                    found,
+                }) => {
+                    assert_eq!(cmd, spec.name);
+                    assert_eq!(expected, spec.args.len());
+                    assert_eq!(found, spec.args.len() + 1);
+                }
+                other => panic!("{}: expected too many arguments, got {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        match "goto 0".parse::<Command>() {
+            Err(ParseCommandError::InvalidArgument { arg, ref value, .. }) => {
+                assert_eq!(arg, "line[:column]");
+                assert_eq!(value, "0");
+            }
+            other => panic!("expected an invalid argument, got {:?}", other),
+        }
+        assert!("help no-such-command".parse::<Command>().is_err());
+        assert!("search foo x".parse::<Command>().is_err());
+        assert!("set wrap=maybe".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn ranges_and_counts() {
+        match parse("10 dl") {
+            Command::Ranged(range, _) => assert_eq!(range, "10".parse().unwrap()),
+            other => panic!("expected a range, got {:?}", other),
+        }
+        match parse("10 md") {
+            Command::Repeat(10, _) => {}
+            other => panic!("expected a count, got {:?}", other),
+        }
+        assert!("0 dl".parse::<Command>().is_err());
+        assert!("20,10 dl".parse::<Command>().is_err());
+        assert!("10,20 md".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn sequences_split_outside_quotes() {
+        match parse(r"search 'a;b'; sn") {
+            Command::Sequence(ref cmds) => {
+                assert_eq!(cmds.len(), 2);
+                assert_eq!(cmds[0].to_string(), parse("search 'a;b'").to_string());
+            }
+            other => panic!("expected a sequence, got {:?}", other),
+        }
+        assert!(
+            !matches!(parse(r#"search "x;y""#), Command::Sequence(_)),
+            "split inside double quotes"
+        );
+        assert!(
+            !matches!(parse(r"search a\;b"), Command::Sequence(_)),
+            "split an escaped ;"
+        );
+    }
+
+    #[test]
+    fn plugin_commands() {
+        match "foo".parse::<Command>() {
+            Err(ParseCommandError::UnknownCommand(ref name)) => assert_eq!(name, "foo"),
+            other => panic!("expected an unknown command, got {:?}", other),
+        }
+        let is_plugin_command = |name: &str| name == "foo";
+        match Command::parse("2 foo; s", is_plugin_command) {
+            Ok(Command::Sequence(ref cmds)) => assert_eq!(cmds[0].to_string(), "2 foo"),
+            other => panic!("expected a sequence, got {:?}", other),
+        }
+        assert!(Command::parse("fo", is_plugin_command).is_err());
+        assert!(Command::parse("foo bar", is_plugin_command).is_err());
+    }
+
+    #[test]
+    fn commands_table_is_well_formed() {
+        for row in commands_table().lines() {
+            assert_eq!(row.matches('|').count(), 4, "{}", row);
+        }
+    }
+
+    #[test]
+    fn counts_are_capped() {
+        assert!("10000md".parse::<Command>().is_ok());
+        match "10001md".parse::<Command>() {
+            Err(ParseCommandError::InvalidCount(ref count)) => assert_eq!(count, "10001"),
+            other => panic!("expected an invalid count, got {:?}", other),
+        }
+        assert!("99999999999999999999md".parse::<Command>().is_err());
+        assert!("0md".parse::<Command>().is_err());
+        match format!("play q {}", MAX_COUNT + 1).parse::<Command>() {
+            Err(ParseCommandError::InvalidArgument { ref reason, .. }) => {
+                assert_eq!(
+                    *reason,
+                    format!("expected a number from 1 to {}", MAX_COUNT)
+                )
+            }
+            other => panic!("expected an invalid argument, got {:?}", other),
+        }
+    }
 }
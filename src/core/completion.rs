@@ -0,0 +1,204 @@
+//! Completion of the command prompt input. What is completed depends
+//! on the position in the input: command names for the first word,
+//! and then whatever the argument of the command takes, as declared in
+//! the command registry. Only the last command of the input is
+//! completed, when several are separated by `;`.
+use std::fs;
+
+use core::{
+    escape, quote, split_commands, tokenize, ArgKind, CommandSpec, OptionSpec, Range, Word,
+    COMMANDS, OPTIONS,
+};
+
+/// The values the editor knows about, that some arguments can be
+/// completed with.
+#[derive(Debug, Default)]
+pub struct CompletionContext {
+    /// Names of the available themes.
+    pub themes: Vec<String>,
+    /// Names of the languages the core knows about.
+    pub languages: Vec<String>,
+    /// Names of the open buffers.
+    pub buffers: Vec<String>,
+    /// Names of the plugins of the current buffer.
+    pub plugins: Vec<String>,
+    /// Names of the commands provided by these plugins.
+    pub plugin_commands: Vec<String>,
+}
+
+/// Find the completions of the word that ends at the end of `input`.
+/// Return the position where this word starts, and the strings it can
+/// be replaced with, already quoted if needed.
+pub fn complete(input: &str, ctx: &CompletionContext) -> (usize, Vec<String>) {
+    let last = split_commands(input).pop().unwrap_or("");
+    let offset = input.len() - last.len();
+    let (start, candidates) = complete_command(last, ctx);
+    (offset + start, candidates)
+}
+
+fn complete_command(input: &str, ctx: &CompletionContext) -> (usize, Vec<String>) {
+    // Skip the range or the count the command may start with, e.g.
+    // "10,20 " or "10 ".
+    let trimmed = input.trim_start();
+    let range_len = Range::prefix_len(trimmed);
+    if range_len > 0 && range_len < trimmed.len() {
+        let offset = input.len() - trimmed.len() + range_len;
+        let (start, candidates) = complete_command(&input[offset..], ctx);
+        return (offset + start, candidates);
+    }
+
+    let start = word_start(input);
+    let raw = &input[start..];
+    let previous = tokenize(&input[..start]).unwrap_or_default();
+
+    // Completing the command name. It may be prefixed by a count.
+    if previous.is_empty() {
+        let count_len = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+        let (count, prefix) = raw.split_at(count_len);
+        let candidates = COMMANDS
+            .iter()
+            .map(|spec| spec.name)
+            .chain(ctx.plugin_commands.iter().map(String::as_str))
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| format!("{}{}", count, quote(name)))
+            .collect();
+        return (start, candidates);
+    }
+
+    let name = previous[0].text();
+    let name = name.trim_start_matches(|c: char| c.is_ascii_digit());
+    let kind = match CommandSpec::find(name).and_then(|spec| spec.args.get(previous.len() - 1)) {
+        Some(arg) => arg.kind,
+        None => return (start, Vec::new()),
+    };
+    let word = partial_word(raw);
+    let candidates = match kind {
+        ArgKind::File => complete_path(raw, &word),
+        ArgKind::Theme => complete_from(&word.text(), &ctx.themes),
+        ArgKind::Language => complete_from(&word.text(), &ctx.languages),
+        ArgKind::Buffer => complete_from(&word.text(), &ctx.buffers),
+        ArgKind::Plugin => complete_from(&word.text(), &ctx.plugins),
+        ArgKind::PluginAction => {
+            complete_from(&word.text(), &["start".to_string(), "stop".to_string()])
+        }
+        ArgKind::Command => {
+            let names: Vec<String> = COMMANDS.iter().map(|spec| spec.name.to_string()).collect();
+            complete_from(&word.text(), &names)
+        }
+        ArgKind::Setting => complete_setting(&word.text()),
+        ArgKind::Position
+        | ArgKind::Register
+        | ArgKind::Count
+        | ArgKind::Text
+        | ArgKind::SearchFlags => Vec::new(),
+    };
+    (start, candidates)
+}
+
+/// Find where the last word of the input starts, taking quotes and
+/// escapes into account.
+fn word_start(input: &str) -> usize {
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => start = i + c.len_utf8(),
+            (None, _) => {}
+        }
+    }
+    start
+}
+
+/// Parse a word that is still being typed, and may lack its closing
+/// quote.
+fn partial_word(raw: &str) -> Word {
+    tokenize(raw)
+        .or_else(|_| tokenize(&format!("{}'", raw)))
+        .or_else(|_| tokenize(&format!("{}\"", raw)))
+        .ok()
+        .and_then(|mut words| words.pop())
+        .unwrap_or_default()
+}
+
+fn complete_from(prefix: &str, values: &[String]) -> Vec<String> {
+    values
+        .iter()
+        .filter(|value| value.starts_with(prefix))
+        .map(|value| quote(value))
+        .collect()
+}
+
+/// Complete the name of an option, or its value after `=`.
+fn complete_setting(prefix: &str) -> Vec<String> {
+    let values: Vec<String> = match prefix.find('=') {
+        Some(i) => match OptionSpec::find(&prefix[..i]) {
+            Some(spec) => spec
+                .values()
+                .iter()
+                .map(|value| format!("{}={}", spec.name, value))
+                .collect(),
+            None => Vec::new(),
+        },
+        None => OPTIONS.iter().map(|spec| spec.name.to_string()).collect(),
+    };
+    complete_from(prefix, &values)
+}
+
+/// Complete a path with the entries of the directory it points to.
+/// Hidden files are only listed if the file name starts with a dot.
+fn complete_path(raw: &str, word: &Word) -> Vec<String> {
+    if raw == "~" {
+        return vec!["~/".to_string()];
+    }
+    let path = word.expand().unwrap_or_else(|_| word.text());
+    let (dir, prefix) = match path.rfind('/') {
+        Some(i) => path.split_at(i + 1),
+        None => ("", &path[..]),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("cannot complete {}: {}", path, e);
+            return Vec::new();
+        }
+    };
+    let mut names: Vec<(String, bool)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            Some((name, is_dir))
+        })
+        .collect();
+    names.sort();
+
+    // Keep what the user typed for the directory, e.g. `~/`, unless
+    // the word is quoted, in which case it is simpler to quote the
+    // whole path again.
+    let quoted = raw.contains(['\'', '"']);
+    let raw_dir = &raw[..raw.rfind('/').map(|i| i + 1).unwrap_or(0)];
+    names
+        .into_iter()
+        .map(|(name, is_dir)| {
+            let slash = if is_dir { "/" } else { "" };
+            if quoted {
+                quote(&format!("{}{}{}", dir, name, slash))
+            } else {
+                format!("{}{}{}", raw_dir, escape(&name), slash)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,84 @@
+//! History of the commands entered in the command prompt. The history
+//! is saved to a file, one command per line, so that it is available
+//! across sessions.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+pub struct History {
+    /// The commands, from the oldest to the most recent. A command
+    /// appears only once.
+    entries: Vec<String>,
+
+    /// Maximum number of commands to keep.
+    size: usize,
+
+    /// File the history is saved to.
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Create a history keeping at most `size` commands. If a path is
+    /// given, the history is loaded from this file, and saved to it
+    /// every time a command is added.
+    pub fn new(path: Option<PathBuf>, size: usize) -> Self {
+        let mut entries = match path {
+            Some(ref path) if path.exists() => load(path).unwrap_or_else(|e| {
+                error!("failed to load history from {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        if entries.len() > size {
+            entries.drain(..entries.len() - size);
+        }
+        History {
+            entries,
+            size,
+            path,
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Add a command to the history. If it was already there, it is
+    /// moved to the end.
+    pub fn add(&mut self, entry: &str) {
+        let entry = entry.trim();
+        if entry.is_empty() || entry.contains('\n') || self.size == 0 {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > self.size {
+            self.entries.remove(0);
+        }
+        if let Some(ref path) = self.path {
+            if let Err(e) = save(path, &self.entries) {
+                error!("failed to save history to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn load(path: &PathBuf) -> io::Result<Vec<String>> {
+    let mut entries: Vec<String> = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.is_empty() {
+            entries.retain(|e| e != &line);
+            entries.push(line);
+        }
+    }
+    Ok(entries)
+}
+
+fn save(path: &PathBuf, entries: &[String]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    for entry in entries {
+        writeln!(file, "{}", entry)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,170 @@
+//! Decoding of the input sequences termion doesn't understand.
+//!
+//! termion reports key presses and mouse events that come with
+//! modifiers (Shift, Alt, Ctrl) as `Event::Unsupported`, with the raw
+//! escape sequence. This module turns these sequences into events we
+//! can bind.
+//!
+//! It also converts events back to the sequences the terminal sends,
+//! so that they can be saved and read again later.
+use std::str;
+
+use termion::event::{Event, Key, MouseButton, MouseEvent};
+use termion::input::TermRead;
+
+/// Modifier keys held during a key press or a mouse event.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+/// An input event decoded from a sequence termion doesn't support.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Key(Key, Modifiers),
+    Mouse(MouseEvent, Modifiers),
+}
+
+/// Decode the raw bytes of an `Event::Unsupported`.
+pub fn parse_unsupported(seq: &[u8]) -> Option<InputEvent> {
+    if seq.starts_with(b"\x1b[<") {
+        parse_sgr_mouse(&seq[3..])
+    } else if seq.starts_with(b"\x1b[") {
+        parse_modified_key(&seq[2..])
+    } else {
+        None
+    }
+}
+
+/// Parse the parameters of a key sequence with modifiers, either
+/// `ESC [ 1 ; modifiers key` for arrows, Home and End, or
+/// `ESC [ code ; modifiers ~` for the other special keys.
+fn parse_modified_key(params: &[u8]) -> Option<InputEvent> {
+    let (last, params) = params.split_last()?;
+    let mut nums = str::from_utf8(params)
+        .ok()?
+        .split(';')
+        .map(|n| n.parse::<u8>().ok());
+    let code = nums.next()??;
+    let modifiers = nums.next()??.checked_sub(1)?;
+
+    let key = match (*last, code) {
+        (b'A', 1) => Key::Up,
+        (b'B', 1) => Key::Down,
+        (b'C', 1) => Key::Right,
+        (b'D', 1) => Key::Left,
+        (b'H', 1) => Key::Home,
+        (b'F', 1) => Key::End,
+        (b'~', 2) => Key::Insert,
+        (b'~', 3) => Key::Delete,
+        (b'~', 5) => Key::PageUp,
+        (b'~', 6) => Key::PageDown,
+        _ => return None,
+    };
+    let modifiers = Modifiers {
+        shift: modifiers & 1 != 0,
+        alt: modifiers & 2 != 0,
+        ctrl: modifiers & 4 != 0,
+    };
+    Some(InputEvent::Key(key, modifiers))
+}
+
+/// Parse the parameters of a SGR mouse sequence
+/// (`ESC [ < button ; column ; row M`). A trailing `m` instead of `M`
+/// means the button was released.
+fn parse_sgr_mouse(params: &[u8]) -> Option<InputEvent> {
+    let (last, params) = params.split_last()?;
+    let mut nums = str::from_utf8(params)
+        .ok()?
+        .split(';')
+        .map(|n| n.parse::<u16>().ok());
+    let cb = nums.next()??;
+    let cx = nums.next()??;
+    let cy = nums.next()??;
+
+    let modifiers = Modifiers {
+        shift: cb & 4 != 0,
+        alt: cb & 8 != 0,
+        ctrl: cb & 16 != 0,
+    };
+
+    let event = if *last == b'm' {
+        MouseEvent::Release(cx, cy)
+    } else if cb & 32 != 0 {
+        MouseEvent::Hold(cx, cy)
+    } else if cb & 64 != 0 {
+        match cb & 0b11 {
+            0 => MouseEvent::Press(MouseButton::WheelUp, cx, cy),
+            1 => MouseEvent::Press(MouseButton::WheelDown, cx, cy),
+            _ => return None,
+        }
+    } else {
+        match cb & 0b11 {
+            0 => MouseEvent::Press(MouseButton::Left, cx, cy),
+            1 => MouseEvent::Press(MouseButton::Middle, cx, cy),
+            2 => MouseEvent::Press(MouseButton::Right, cx, cy),
+            _ => MouseEvent::Release(cx, cy),
+        }
+    };
+    Some(InputEvent::Mouse(event, modifiers))
+}
+
+/// Parse the first event of a sequence sent by the terminal.
+pub fn parse_event(seq: &[u8]) -> Option<Event> {
+    match seq.events().next() {
+        Some(Ok(event)) => Some(event),
+        _ => None,
+    }
+}
+
+/// Get the escape sequence a terminal sends for an event. This is the
+/// reverse of the parsing termion does.
+pub fn event_to_bytes(event: &Event) -> Option<Vec<u8>> {
+    let seq = match *event {
+        Event::Key(key) => match key {
+            Key::Backspace => b"\x7f".to_vec(),
+            Key::Left => b"\x1b[D".to_vec(),
+            Key::Right => b"\x1b[C".to_vec(),
+            Key::Up => b"\x1b[A".to_vec(),
+            Key::Down => b"\x1b[B".to_vec(),
+            Key::Home => b"\x1b[H".to_vec(),
+            Key::End => b"\x1b[F".to_vec(),
+            Key::PageUp => b"\x1b[5~".to_vec(),
+            Key::PageDown => b"\x1b[6~".to_vec(),
+            Key::Delete => b"\x1b[3~".to_vec(),
+            Key::Insert => b"\x1b[2~".to_vec(),
+            Key::F(n @ 1..=4) => vec![0x1b, b'O', b'P' + n - 1],
+            Key::F(5) => b"\x1b[15~".to_vec(),
+            Key::F(n @ 6..=10) => format!("\x1b[{}~", n + 11).into_bytes(),
+            Key::F(n @ 11..=12) => format!("\x1b[{}~", n + 12).into_bytes(),
+            Key::Char(c) => c.to_string().into_bytes(),
+            Key::Alt(c) => format!("\x1b{}", c).into_bytes(),
+            Key::Ctrl(c @ 'a'..='z') => vec![c as u8 - b'a' + 1],
+            Key::Ctrl(c @ '4'..='7') => vec![c as u8 - b'4' + 0x1c],
+            Key::Null => vec![0],
+            Key::Esc => vec![0x1b],
+            _ => return None,
+        },
+        Event::Mouse(mouse_event) => {
+            let (button, x, y, end) = match mouse_event {
+                MouseEvent::Press(button, x, y) => {
+                    let button = match button {
+                        MouseButton::Left => 0,
+                        MouseButton::Middle => 1,
+                        MouseButton::Right => 2,
+                        MouseButton::WheelUp => 64,
+                        MouseButton::WheelDown => 65,
+                    };
+                    (button, x, y, 'M')
+                }
+                MouseEvent::Release(x, y) => (0, x, y, 'm'),
+                MouseEvent::Hold(x, y) => (32, x, y, 'M'),
+            };
+            format!("\x1b[<{};{};{}{}", button, x, y, end).into_bytes()
+        }
+        Event::Unsupported(ref seq) => seq.clone(),
+    };
+    Some(seq)
+}
@@ -0,0 +1,173 @@
+//! Keyboard macros. A macro is the sequence of commands and input
+//! events sent to the current view while it was being recorded. Macros
+//! are stored in named registers, and can be saved to a file so that
+//! they are available across sessions.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use serde_json::{self, Value};
+use termion::event::Event;
+
+use core::{event_to_bytes, parse_event, Command, MAX_COUNT};
+
+/// A step of a macro.
+#[derive(Debug, Clone)]
+pub enum MacroStep {
+    /// A command run from the command prompt.
+    Command(Command),
+    /// An input event sent to the current view, and its repeat count.
+    Input(Event, usize),
+    /// Text pasted in the current view.
+    Paste(String),
+}
+
+pub struct Macros {
+    /// The recorded macros, by register.
+    registers: HashMap<String, Vec<MacroStep>>,
+
+    /// The register being recorded, and the steps recorded so far.
+    recording: Option<(String, Vec<MacroStep>)>,
+
+    /// The registers being played. A macro that plays itself would
+    /// never end, so we keep track of them to prevent it.
+    playing: Vec<String>,
+
+    /// File the macros are saved to, if they should be persisted.
+    path: Option<PathBuf>,
+}
+
+impl Macros {
+    /// Create an empty set of macros. If a path is given, macros are
+    /// loaded from this file, and saved to it every time a recording
+    /// ends.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let registers = match path {
+            Some(ref path) if path.exists() => load(path).unwrap_or_else(|e| {
+                error!("failed to load macros from {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            _ => HashMap::new(),
+        };
+        Macros {
+            registers,
+            recording: None,
+            playing: Vec::new(),
+            path,
+        }
+    }
+
+    pub fn start_recording(&mut self, register: String) {
+        info!("recording macro in register {}", register);
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Stop recording, and return the register the macro was recorded
+    /// in, if a macro was being recorded.
+    pub fn stop_recording(&mut self) -> Option<String> {
+        let (register, steps) = self.recording.take()?;
+        info!("recorded {} steps in register {}", steps.len(), register);
+        self.registers.insert(register.clone(), steps);
+        if let Some(ref path) = self.path {
+            if let Err(e) = save(path, &self.registers) {
+                error!("failed to save macros to {}: {}", path.display(), e);
+            }
+        }
+        Some(register)
+    }
+
+    /// Record a step, if a macro is being recorded. The steps run while
+    /// playing a macro are not recorded: playing the macro is.
+    pub fn record(&mut self, step: MacroStep) {
+        if !self.playing.is_empty() {
+            return;
+        }
+        if let Some((_, ref mut steps)) = self.recording {
+            steps.push(step);
+        }
+    }
+
+    /// Get the steps of the macro in the given register, and mark it as
+    /// being played. Fail if the register is empty or is already being
+    /// played.
+    pub fn start_playing(&mut self, register: &str) -> Result<Vec<MacroStep>, &'static str> {
+        if self.playing.iter().any(|r| r == register) {
+            warn!("macro {} is already being played", register);
+            return Err("the macro is already being played");
+        }
+        let steps = match self.registers.get(register) {
+            Some(steps) => steps.clone(),
+            None => return Err("no macro recorded in this register"),
+        };
+        self.playing.push(register.to_string());
+        Ok(steps)
+    }
+
+    pub fn stop_playing(&mut self) {
+        self.playing.pop();
+    }
+}
+
+/// Save the macros as a JSON object that maps each register to a list
+/// of steps. Input events are saved as the escape sequences that the
+/// terminal sends for them.
+fn save(path: &PathBuf, registers: &HashMap<String, Vec<MacroStep>>) -> io::Result<()> {
+    let mut macros = serde_json::Map::new();
+    for (register, steps) in registers {
+        let steps = steps
+            .iter()
+            .filter_map(|step| match *step {
+                MacroStep::Command(ref cmd) => Some(json!({ "command": cmd.to_string() })),
+                MacroStep::Input(ref event, count) => {
+                    let seq = event_to_bytes(event)?;
+                    Some(json!({ "input": seq, "count": count }))
+                }
+                MacroStep::Paste(ref text) => Some(json!({ "paste": text })),
+            })
+            .collect();
+        macros.insert(register.clone(), Value::Array(steps));
+    }
+    let mut file = File::create(path)?;
+    file.write_all(Value::Object(macros).to_string().as_bytes())
+}
+
+fn load(path: &PathBuf) -> io::Result<HashMap<String, Vec<MacroStep>>> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    let macros: Value = serde_json::from_str(&content)?;
+
+    let mut registers = HashMap::new();
+    if let Some(macros) = macros.as_object() {
+        for (register, steps) in macros {
+            let steps = steps
+                .as_array()
+                .map(|steps| steps.iter().filter_map(parse_step).collect())
+                .unwrap_or_default();
+            registers.insert(register.clone(), steps);
+        }
+    }
+    Ok(registers)
+}
+
+fn parse_step(step: &Value) -> Option<MacroStep> {
+    if let Some(cmd) = step.get("command").and_then(Value::as_str) {
+        // Plugin commands were known when they were recorded, but the
+        // plugins that provide them have not started yet.
+        return Command::parse(cmd, |_| true).ok().map(MacroStep::Command);
+    }
+    if let Some(text) = step.get("paste").and_then(Value::as_str) {
+        return Some(MacroStep::Paste(text.to_string()));
+    }
+    let seq: Vec<u8> = step
+        .get("input")?
+        .as_array()?
+        .iter()
+        .filter_map(|byte| byte.as_u64().map(|byte| byte as u8))
+        .collect();
+    let count = step.get("count").and_then(Value::as_u64).unwrap_or(1);
+    Some(MacroStep::Input(
+        parse_event(&seq)?,
+        (count as usize).min(MAX_COUNT),
+    ))
+}
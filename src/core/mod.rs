@@ -1,8 +1,41 @@
 mod terminal;
-pub use self::terminal::{RenderTarget, Terminal, TerminalEvent};
+pub use self::terminal::{RawEvent, RenderTarget, Terminal, TerminalEvent};
 
 mod tui;
 pub use self::tui::{CoreEvent, Tui, TuiService, TuiServiceBuilder};
 
+mod clipboard;
+pub use self::clipboard::Clipboard;
+
 mod cmd;
-pub use self::cmd::{Command, ParseCommandError};
+pub use self::cmd::{
+    commands_table, help, ArgKind, Command, CommandSpec, ParseCommandError, PluginAction, COMMANDS,
+    MAX_COUNT,
+};
+
+mod range;
+pub use self::range::Range;
+
+mod search;
+pub use self::search::SearchQuery;
+
+mod options;
+pub use self::options::{options_table, OptionSpec, OptionValue, Setting, OPTIONS};
+
+mod tokenizer;
+pub use self::tokenizer::{escape, quote, split_commands, tokenize, Word};
+
+mod completion;
+pub use self::completion::{complete, CompletionContext};
+
+mod session;
+pub use self::session::{load_session, SessionRecorder};
+
+mod history;
+pub use self::history::History;
+
+mod macros;
+pub use self::macros::{MacroStep, Macros};
+
+mod input;
+pub use self::input::{event_to_bytes, parse_event, parse_unsupported, InputEvent, Modifiers};
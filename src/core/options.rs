@@ -0,0 +1,198 @@
+//! Options changed with `set`, e.g. `set tab_size=2` or `set wrap?`.
+//!
+//! Some options only change how xi-term displays a buffer, the others
+//! are core options, sent to the core with a "modify_user_config"
+//! notification.
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+/// The values an option takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionType {
+    Bool,
+    /// A positive number.
+    Number,
+    /// One of the given names.
+    Choice(&'static [&'static str]),
+}
+
+/// Description of an option.
+#[derive(Debug)]
+pub struct OptionSpec {
+    pub name: &'static str,
+    pub kind: OptionType,
+    /// Whether the option is handled by the core rather than xi-term.
+    pub core: bool,
+    pub description: &'static str,
+}
+
+pub static OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "gutter",
+        kind: OptionType::Choice(&["absolute", "relative", "none"]),
+        core: false,
+        description: "Line numbers: absolute, relative to the cursor, or none",
+    },
+    OptionSpec {
+        name: "wrap",
+        kind: OptionType::Bool,
+        core: false,
+        description: "Wrap the lines wider than the screen",
+    },
+    OptionSpec {
+        name: "whitespace",
+        kind: OptionType::Choice(&["none", "trailing", "all"]),
+        core: false,
+        description: "Show the spaces (as `.`) and tabs (as `>`): none, trailing ones, or all",
+    },
+    OptionSpec {
+        name: "tab_size",
+        kind: OptionType::Number,
+        core: true,
+        description: "Width of a tab, and of an indentation level",
+    },
+    OptionSpec {
+        name: "translate_tabs_to_spaces",
+        kind: OptionType::Bool,
+        core: true,
+        description: "Insert spaces rather than tabs",
+    },
+    OptionSpec {
+        name: "auto_indent",
+        kind: OptionType::Bool,
+        core: true,
+        description: "Indent new lines like the previous one",
+    },
+];
+
+impl OptionSpec {
+    pub fn find(name: &str) -> Option<&'static OptionSpec> {
+        OPTIONS.iter().find(|spec| spec.name == name)
+    }
+
+    /// Check a value against the type of the option.
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        match self.kind {
+            OptionType::Bool => match value {
+                "true" => Ok(OptionValue::Bool(true)),
+                "false" => Ok(OptionValue::Bool(false)),
+                _ => Err("expected true or false".into()),
+            },
+            OptionType::Number => match value.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(OptionValue::Number(n)),
+                _ => Err("expected a positive number".into()),
+            },
+            OptionType::Choice(choices) => match choices.iter().find(|c| **c == value) {
+                Some(choice) => Ok(OptionValue::Choice(choice)),
+                None => Err(format!("expected one of {}", choices.join(", "))),
+            },
+        }
+    }
+
+    /// The values the option takes, for completion.
+    pub fn values(&self) -> &'static [&'static str] {
+        match self.kind {
+            OptionType::Bool => &["true", "false"],
+            OptionType::Number => &[],
+            OptionType::Choice(choices) => choices,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionValue {
+    Bool(bool),
+    Number(u64),
+    Choice(&'static str),
+}
+
+impl OptionValue {
+    /// The value as sent to the core.
+    pub fn to_json(self) -> Value {
+        match self {
+            OptionValue::Bool(b) => Value::from(b),
+            OptionValue::Number(n) => Value::from(n),
+            OptionValue::Choice(choice) => Value::from(choice),
+        }
+    }
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptionValue::Bool(b) => write!(f, "{}", b),
+            OptionValue::Number(n) => write!(f, "{}", n),
+            OptionValue::Choice(choice) => write!(f, "{}", choice),
+        }
+    }
+}
+
+/// Get a Markdown table of the options, for the documentation.
+pub fn options_table() -> String {
+    let mut table = String::from(
+        "| Option | Values | Handled by | Description |\n\
+         | ------ | ------ | ---------- | ----------- |\n",
+    );
+    for spec in OPTIONS {
+        let values = match spec.kind {
+            OptionType::Number => "a number".to_string(),
+            _ => spec
+                .values()
+                .iter()
+                .map(|value| format!("`{}`", value))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        table.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            spec.name,
+            values,
+            if spec.core { "core" } else { "xi-term" },
+            spec.description
+        ));
+    }
+    table
+}
+
+/// An argument of `set`: `name=value` to change an option, or `name?`
+/// to show its value.
+#[derive(Debug, Clone)]
+pub struct Setting {
+    pub spec: &'static OptionSpec,
+    /// The new value, or `None` to show the current one.
+    pub value: Option<OptionValue>,
+}
+
+impl FromStr for Setting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Setting, Self::Err> {
+        let (name, value) = match s.find('=') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None if s.ends_with('?') => (&s[..s.len() - 1], None),
+            None => return Err("expected <option>=<value> or <option>?".into()),
+        };
+        let spec = match OptionSpec::find(name) {
+            Some(spec) => spec,
+            None => return Err(format!("no option named {}", name)),
+        };
+        Ok(Setting {
+            spec,
+            value: match value {
+                Some(value) => Some(spec.parse(value)?),
+                None => None,
+            },
+        })
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(ref value) => write!(f, "{}={}", self.spec.name, value),
+            None => write!(f, "{}?", self.spec.name),
+        }
+    }
+}
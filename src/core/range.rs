@@ -0,0 +1,308 @@
+//! Ranges of lines that the commands acting on text apply to, e.g.
+//! `10,20 delete-lines` or `% sort`.
+//!
+//! A range is either `%`, for the whole buffer, or one or two line
+//! addresses separated by a comma. An address is a line number
+//! starting at 1, `.` for the line of the cursor, or `$` for the last
+//! line, optionally followed by an offset, e.g. `.+3` or `$-1`. An
+//! offset alone is relative to the line of the cursor.
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// A line in a range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line {
+    /// A line number, starting at 1.
+    Number(u64),
+    /// The line of the cursor.
+    Current,
+    /// The last line of the buffer.
+    Last,
+}
+
+/// A line address in a range: a line, and an offset from it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Address {
+    pub line: Line,
+    pub offset: i64,
+}
+
+impl Address {
+    const fn new(line: Line) -> Self {
+        Address { line, offset: 0 }
+    }
+
+    /// Add the offset to a line. Return `None` if the result is too
+    /// large.
+    fn add_offset(&self, line: u64) -> Option<i64> {
+        i64::try_from(line).ok()?.checked_add(self.offset)
+    }
+
+    /// Get the line number, starting at 1, given the line of the cursor
+    /// and the number of lines of the buffer.
+    fn resolve(&self, current: u64, count: u64) -> Result<u64, String> {
+        let line = match self.line {
+            Line::Number(n) => n,
+            Line::Current => current,
+            Line::Last => count,
+        };
+        match self.add_offset(line) {
+            Some(line) if line < 1 => {
+                Err(format!("line {} is before the start of the buffer", line))
+            }
+            Some(line) if line as u64 <= count => Ok(line as u64),
+            Some(line) => Err(format!(
+                "line {} is past the end of the buffer ({} lines)",
+                line, count
+            )),
+            None => Err(format!(
+                "line {} is past the end of the buffer ({} lines)",
+                self, count
+            )),
+        }
+    }
+}
+
+/// A range of lines. Both ends are included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl Range {
+    /// The line of the cursor.
+    pub const CURRENT: Range = Range {
+        start: Address::new(Line::Current),
+        end: Address::new(Line::Current),
+    };
+
+    /// The whole buffer.
+    pub const ALL: Range = Range {
+        start: Address::new(Line::Number(1)),
+        end: Address::new(Line::Last),
+    };
+
+    /// Length of the prefix of a string made of the characters a range
+    /// is made of.
+    pub fn prefix_len(s: &str) -> usize {
+        s.find(|c: char| !(c.is_ascii_digit() || ",.$%+-".contains(c)))
+            .unwrap_or(s.len())
+    }
+
+    /// Whether a string looks like a range rather than a command name
+    /// or a count, i.e. is made of the characters a range is made of,
+    /// and at least one that a count is not made of.
+    pub fn is_range(s: &str) -> bool {
+        !s.is_empty() && Range::prefix_len(s) == s.len() && !s.chars().all(|c| c.is_ascii_digit())
+    }
+
+    /// Get the first and last lines of the range, starting at 1, given
+    /// the line of the cursor and the number of lines of the buffer.
+    pub fn resolve(&self, current: u64, count: u64) -> Result<(u64, u64), String> {
+        let start = self.start.resolve(current, count)?;
+        let end = self.end.resolve(current, count)?;
+        if start > end {
+            return Err(format!(
+                "backwards range {}: {} is after {}",
+                self, start, end
+            ));
+        }
+        Ok((start, end))
+    }
+}
+
+impl FromStr for Line {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Line, Self::Err> {
+        match s {
+            "" => Err("missing line"),
+            "." => Ok(Line::Current),
+            "$" => Ok(Line::Last),
+            s => match s.parse::<u64>() {
+                Ok(0) => Err("lines start at 1"),
+                Ok(n) => Ok(Line::Number(n)),
+                Err(_) => Err("expected a line number, `.` or `$`"),
+            },
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Address, Self::Err> {
+        let (line, offset) = match s.find(['+', '-']) {
+            Some(i) => s.split_at(i),
+            None => (s, ""),
+        };
+        let line = match line {
+            "" if !offset.is_empty() => Line::Current,
+            line => line.parse::<Line>()?,
+        };
+        let offset = match offset {
+            "" => 0,
+            // The sign must be followed by digits only.
+            offset if offset[1..].chars().all(|c| c.is_ascii_digit()) => offset
+                .parse::<i64>()
+                .map_err(|_| "expected a number after + or -")?,
+            _ => return Err("expected a number after + or -"),
+        };
+        Ok(Address { line, offset })
+    }
+}
+
+impl FromStr for Range {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Range, Self::Err> {
+        if s == "%" {
+            return Ok(Range::ALL);
+        }
+        let mut parts = s.splitn(2, ',');
+        let start = parts.next().unwrap_or("").parse::<Address>()?;
+        let end = match parts.next() {
+            Some(end) => end.parse::<Address>()?,
+            None => start,
+        };
+        // Lines that are too large are reported when the range is
+        // resolved.
+        let backwards = match (start.line, end.line) {
+            (Line::Number(a), Line::Number(b)) => match (start.add_offset(a), end.add_offset(b)) {
+                (Some(a), Some(b)) => a > b,
+                _ => false,
+            },
+            _ => false,
+        };
+        if backwards {
+            Err("backwards range")
+        } else {
+            Ok(Range { start, end })
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Line::Number(n) => write!(f, "{}", n),
+            Line::Current => write!(f, "."),
+            Line::Last => write!(f, "$"),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.line)?;
+        if self.offset != 0 {
+            write!(f, "{:+}", self.offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Range::ALL {
+            write!(f, "%")
+        } else if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{},{}", self.start, self.end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(s: &str) -> Range {
+        match s.parse() {
+            Ok(range) => range,
+            Err(e) => panic!("cannot parse {:?}: {}", s, e),
+        }
+    }
+
+    #[test]
+    fn parse_and_resolve() {
+        // The cursor is on line 5 of a 20 lines buffer.
+        for &(s, expected) in &[
+            ("%", (1, 20)),
+            (".", (5, 5)),
+            ("$", (20, 20)),
+            ("10", (10, 10)),
+            ("3,7", (3, 7)),
+            (".,$", (5, 20)),
+            (".+3", (8, 8)),
+            ("$-1", (19, 19)),
+            ("-2,+2", (3, 7)),
+            ("1,.-1", (1, 4)),
+        ] {
+            assert_eq!(range(s).resolve(5, 20), Ok(expected), "{}", s);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in &["%", ".", "$", "10", "3,7", ".,$", ".+3", "$-1", ".-2,.+2"] {
+            assert_eq!(range(s).to_string(), *s);
+        }
+        assert_eq!(range("1,$").to_string(), "%");
+        assert_eq!(range("10,10").to_string(), "10");
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        for s in &[
+            "", ",", "0", "1,0", "20,10", "5+1,5", "a", ".+", "$-x", "1,2,3",
+        ] {
+            assert!(s.parse::<Range>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        assert!(range("21").resolve(5, 20).is_err());
+        assert!(range("$+1").resolve(5, 20).is_err());
+        assert!(range(".-5").resolve(5, 20).is_err());
+        assert!(range("15,$").resolve(5, 10).is_err());
+        // Backwards once the cursor is known.
+        assert!(range(".,1").resolve(5, 20).is_err());
+        assert!(range("$,.").resolve(5, 20).is_err());
+        assert_eq!(range(".,1").resolve(1, 20), Ok((1, 1)));
+    }
+
+    #[test]
+    fn overflow() {
+        let max = i64::MAX;
+        for s in &[
+            format!(".+{}", max),
+            format!("$+{}", max),
+            format!("{}", u64::MAX),
+            format!("{}+1", max),
+            format!("{},{}", u64::MAX, max),
+        ] {
+            match range(s).resolve(5, 20) {
+                Err(e) => assert!(e.contains("past the end"), "{}: {}", s, e),
+                Ok(lines) => panic!("{} resolved to {:?}", s, lines),
+            }
+        }
+        assert!(range(&format!("1-{}", max)).resolve(5, 20).is_err());
+        assert!(range(&format!(".-{}", max)).resolve(5, 20).is_err());
+    }
+
+    #[test]
+    fn prefixes() {
+        assert!(Range::is_range("%"));
+        assert!(Range::is_range("10,20"));
+        assert!(Range::is_range(".+1"));
+        assert!(!Range::is_range("10"));
+        assert!(!Range::is_range(""));
+        assert_eq!(Range::prefix_len("10,20 sort"), 5);
+        assert_eq!(Range::prefix_len("dl"), 0);
+    }
+}
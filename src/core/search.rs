@@ -0,0 +1,44 @@
+//! Search queries, as sent to the core's `find`.
+
+/// Text to search for, and how to match it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    pub case_sensitive: bool,
+    pub whole_words: bool,
+    pub regex: bool,
+}
+
+impl SearchQuery {
+    /// Build a query from its text and flags: `c` for a case sensitive
+    /// search, `w` to match whole words only and `r` for a regex. The
+    /// flags are expected to be valid.
+    pub fn new(text: String, flags: &str) -> Self {
+        SearchQuery {
+            text,
+            case_sensitive: flags.contains('c'),
+            whole_words: flags.contains('w'),
+            regex: flags.contains('r'),
+        }
+    }
+
+    /// Check that a string is made of valid search flags.
+    pub fn valid_flags(flags: &str) -> bool {
+        !flags.is_empty() && flags.chars().all(|c| "cwr".contains(c))
+    }
+
+    /// The flags of the query, as given to `new`.
+    pub fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.case_sensitive {
+            flags.push('c');
+        }
+        if self.whole_words {
+            flags.push('w');
+        }
+        if self.regex {
+            flags.push('r');
+        }
+        flags
+    }
+}
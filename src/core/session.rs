@@ -0,0 +1,93 @@
+//! Recording and replay of terminal sessions. Every event the terminal
+//! sends is saved with the time at which it was received, so that a
+//! session can be played again exactly, for instance to reproduce a
+//! rendering bug together with the RPC log.
+//!
+//! A session file contains one JSON object per line, with the time in
+//! milliseconds since the start of the session and the event: either
+//! `"resize": [columns, rows]`, `"input": [bytes]` with the sequence
+//! sent by the terminal, or `"paste": "text"`.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::{self, Value};
+
+use core::{parse_event, RawEvent, TerminalEvent};
+
+pub struct SessionRecorder {
+    /// When the recording started.
+    start: Instant,
+
+    /// The session file. It is written line by line, so that the
+    /// session is saved even if we crash.
+    file: LineWriter<File>,
+}
+
+impl SessionRecorder {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        Ok(SessionRecorder {
+            start: Instant::now(),
+            file: LineWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Record an event. Input events are saved as the bytes `raw` they
+    /// were read from, so that they are replayed exactly, even if we
+    /// don't know them.
+    pub fn record(&mut self, event: &TerminalEvent, raw: &[u8]) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        let time = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        let line = match *event {
+            TerminalEvent::Resize((columns, rows)) => {
+                json!({ "time": time, "resize": [columns, rows] })
+            }
+            TerminalEvent::Input(_) => json!({ "time": time, "input": raw }),
+            TerminalEvent::Paste(ref text) => json!({ "time": time, "paste": text }),
+        };
+        writeln!(self.file, "{}", line)
+    }
+}
+
+/// Read a session file, and return its events with the time at which
+/// they should be sent, and the bytes of the input events. Lines that
+/// cannot be parsed are skipped.
+pub fn load_session(path: &Path) -> io::Result<Vec<(Duration, RawEvent)>> {
+    let mut events = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line)
+            .ok()
+            .and_then(|v| parse_line(&v))
+        {
+            Some(event) => events.push(event),
+            None => warn!("{}:{}: invalid session event", path.display(), i + 1),
+        }
+    }
+    Ok(events)
+}
+
+fn parse_line(line: &Value) -> Option<(Duration, RawEvent)> {
+    let time = Duration::from_millis(line.get("time")?.as_u64()?);
+    if let Some(size) = line.get("resize").and_then(Value::as_array) {
+        let columns = size.first()?.as_u64()? as u16;
+        let rows = size.get(1)?.as_u64()? as u16;
+        let event = TerminalEvent::Resize((columns, rows));
+        return Some((time, (event, Vec::new())));
+    }
+    if let Some(text) = line.get("paste").and_then(Value::as_str) {
+        let event = TerminalEvent::Paste(text.to_string());
+        return Some((time, (event, Vec::new())));
+    }
+    let seq: Vec<u8> = line
+        .get("input")?
+        .as_array()?
+        .iter()
+        .filter_map(|byte| byte.as_u64().map(|byte| byte as u8))
+        .collect();
+    Some((time, (TerminalEvent::Input(parse_event(&seq)?), seq)))
+}
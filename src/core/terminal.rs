@@ -1,6 +1,7 @@
-use std::io::{self, Stdout};
+use std::io::{self, Stdout, Write};
+use std::path::Path;
 use std::thread::{sleep, spawn};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::{Async, Poll, Sink, Stream};
@@ -9,50 +10,134 @@ use failure::{Error, ResultExt};
 
 use termion::event::Event;
 use termion::input::MouseTerminal;
-use termion::input::TermRead;
+use termion::input::TermReadEventsAndRaw;
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::screen::AlternateScreen;
 use termion::terminal_size;
 
+use core::{load_session, SessionRecorder};
+
 /// Simple type alias for the Write implementer we render to.
 pub type RenderTarget = MouseTerminal<AlternateScreen<RawTerminal<Stdout>>>;
 
+/// Escape sequence that enables bracketed paste mode.
+const ENABLE_BRACKETED_PASTE: &str = "\x1b[?2004h";
+/// Escape sequence that disables bracketed paste mode.
+const DISABLE_BRACKETED_PASTE: &str = "\x1b[?2004l";
+/// Sequence sent by the terminal before pasted text.
+const PASTE_START: &[u8] = b"\x1b[200~";
+/// Sequence sent by the terminal after pasted text.
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// An event read from the terminal, and the bytes it was read from
+/// (empty for the events that are not input events).
+pub type RawEvent = (TerminalEvent, Vec<u8>);
+
 pub struct Terminal {
     size: UnboundedReceiver<(u16, u16)>,
-    stdin: UnboundedReceiver<Event>,
+    stdin: UnboundedReceiver<RawEvent>,
     stdout: RenderTarget,
+    /// Records the events we receive, if the session is being recorded.
+    recorder: Option<SessionRecorder>,
 }
 
 impl Terminal {
-    pub fn new() -> Result<Self, Error> {
+    /// Create a new terminal. If `record` is given, the events are
+    /// saved to this file. If `replay` is given, the events are read
+    /// from this file, and the actual input is only listened to once
+    /// they have all been sent.
+    pub fn new(record: Option<&Path>, replay: Option<&Path>) -> Result<Self, Error> {
         let (stdin_tx, stdin_rx) = unbounded();
         let (size_tx, size_rx) = unbounded();
-        let stdout = MouseTerminal::from(AlternateScreen::from(
+        let mut stdout = MouseTerminal::from(AlternateScreen::from(
             io::stdout()
                 .into_raw_mode()
                 .context("Failed to put terminal into raw mode")?,
         ));
+        write!(stdout, "{}", ENABLE_BRACKETED_PASTE)
+            .context("Failed to enable bracketed paste mode")?;
+
+        let recorder = match record {
+            Some(path) => {
+                Some(SessionRecorder::new(path).context("Failed to create the session file")?)
+            }
+            None => None,
+        };
 
         let term = Terminal {
             stdin: stdin_rx,
             size: size_rx,
             stdout,
+            recorder,
         };
 
-        Terminal::start_stdin_listening(stdin_tx);
-        Terminal::start_size_listening(size_tx);
+        match replay {
+            Some(path) => {
+                let events = load_session(path).context("Failed to read the session file")?;
+                Terminal::start_replay(events, stdin_tx, size_tx);
+            }
+            None => {
+                Terminal::start_stdin_listening(stdin_tx);
+                Terminal::start_size_listening(size_tx);
+            }
+        }
         Ok(term)
     }
 
-    fn start_stdin_listening(tx: UnboundedSender<Event>) {
+    fn start_replay(
+        events: Vec<(Duration, RawEvent)>,
+        tx: UnboundedSender<RawEvent>,
+        size_tx: UnboundedSender<(u16, u16)>,
+    ) {
+        let mut tx = tx;
+        spawn(move || {
+            info!("replaying {} events", events.len());
+            let start = Instant::now();
+            for (time, event) in events {
+                let elapsed = start.elapsed();
+                if time > elapsed {
+                    sleep(time - elapsed);
+                }
+                let _ = tx.start_send(event).unwrap();
+                let _ = tx.poll_complete().unwrap();
+            }
+            info!("done replaying events");
+            Terminal::start_stdin_listening(tx);
+            Terminal::start_size_listening(size_tx);
+        });
+    }
+
+    fn start_stdin_listening(tx: UnboundedSender<RawEvent>) {
         let mut tx = tx;
         spawn(move || {
             info!("waiting for input events");
-            for event_res in io::stdin().events() {
+            // Raw bytes received between the start and end of a
+            // bracketed paste. `None` when we're not in a paste.
+            let mut paste: Option<Vec<u8>> = None;
+            for event_res in io::stdin().events_and_raw() {
                 match event_res {
-                    // TODO: at least log the errors
-                    Ok(event) => {
-                        let _ = tx.start_send(event).unwrap();
+                    Ok((event, raw)) => {
+                        let event = match paste.take() {
+                            Some(mut buf) => {
+                                if raw == PASTE_END {
+                                    TerminalEvent::Paste(paste_to_string(&buf))
+                                } else {
+                                    buf.extend_from_slice(&raw);
+                                    paste = Some(buf);
+                                    continue;
+                                }
+                            }
+                            None if raw == PASTE_START => {
+                                paste = Some(Vec::new());
+                                continue;
+                            }
+                            None => TerminalEvent::Input(event),
+                        };
+                        let raw = match event {
+                            TerminalEvent::Input(_) => raw,
+                            _ => Vec::new(),
+                        };
+                        let _ = tx.start_send((event, raw)).unwrap();
                         let _ = tx.poll_complete().unwrap();
                     }
                     Err(e) => error!("{}", e),
@@ -94,9 +179,28 @@ impl Terminal {
     }
 }
 
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if let Err(e) = write!(self.stdout, "{}", DISABLE_BRACKETED_PASTE) {
+            error!("failed to disable bracketed paste mode: {}", e);
+        }
+    }
+}
+
+/// Turn the raw bytes of a bracketed paste into text. Terminals send
+/// line breaks as carriage returns, so they are normalized to `\n`.
+fn paste_to_string(raw: &[u8]) -> String {
+    String::from_utf8_lossy(raw)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+}
+
+#[derive(Debug)]
 pub enum TerminalEvent {
     Resize((u16, u16)),
     Input(Event),
+    /// Text pasted in bracketed paste mode, received as a single block.
+    Paste(String),
 }
 
 impl Stream for Terminal {
@@ -104,12 +208,29 @@ impl Stream for Terminal {
     type Error = ();
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.poll_events()? {
+            Async::Ready(Some((event, raw))) => {
+                if let Some(ref mut recorder) = self.recorder {
+                    if let Err(e) = recorder.record(&event, &raw) {
+                        error!("failed to record event: {}", e);
+                    }
+                }
+                Ok(Async::Ready(Some(event)))
+            }
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl Terminal {
+    fn poll_events(&mut self) -> Poll<Option<RawEvent>, ()> {
         debug!("polling for terminal size events");
         match self.size.poll() {
             Ok(Async::Ready(Some(size))) => {
                 debug!("size event: {:?}", size);
                 let event = TerminalEvent::Resize(size);
-                return Ok(Async::Ready(Some(event)));
+                return Ok(Async::Ready(Some((event, Vec::new()))));
             }
             Ok(Async::Ready(None)) => {
                 warn!("terminal size sender closed the channel");
@@ -124,8 +245,7 @@ impl Stream for Terminal {
         debug!("polling for stdin events");
         match self.stdin.poll() {
             Ok(Async::Ready(Some(event))) => {
-                debug!("stdin event: {:?}", event);
-                let event = TerminalEvent::Input(event);
+                debug!("stdin event: {:?}", event.0);
                 return Ok(Async::Ready(Some(event)));
             }
             Ok(Async::Ready(None)) => {
@@ -0,0 +1,393 @@
+//! Splitting of the command prompt input into words, shell style.
+//!
+//! Words are separated by whitespace. Single quotes keep their content
+//! literally, double quotes keep it except for `\"`, `\\` and `\$`, and
+//! outside of quotes a backslash escapes the next character. Words
+//! that are paths can also be expanded: a leading `~` becomes the home
+//! directory and `$VAR` or `${VAR}` the value of an environment
+//! variable, unless they are quoted with single quotes or escaped.
+//!
+//! Several commands can be given on the same line, separated by `;`.
+use std::env;
+
+use core::ParseCommandError;
+
+/// A word of the command prompt input. It is made of segments that
+/// are either expandable (unquoted or double quoted) or literal.
+#[derive(Debug, Clone, Default)]
+pub struct Word {
+    /// Whether the word starts with an unquoted `~`, which is not part
+    /// of the segments.
+    tilde: bool,
+    segments: Vec<(String, bool)>,
+}
+
+impl Word {
+    /// A word that is not expanded.
+    pub fn literal(text: &str) -> Self {
+        Word {
+            tilde: false,
+            segments: vec![(text.to_string(), false)],
+        }
+    }
+
+    fn push(&mut self, c: char, expandable: bool) {
+        match self.segments.last_mut() {
+            Some(&mut (ref mut text, e)) if e == expandable => text.push(c),
+            _ => self.segments.push((c.to_string(), expandable)),
+        }
+    }
+
+    /// The word with its quotes and escapes removed.
+    pub fn text(&self) -> String {
+        let text: String = self.segments.iter().map(|s| &s.0[..]).collect();
+        if self.tilde {
+            format!("~{}", text)
+        } else {
+            text
+        }
+    }
+
+    /// The word with its quotes and escapes removed, and `~` and the
+    /// environment variables expanded. Return the name of the variable
+    /// if a variable is not defined.
+    pub fn expand(&self) -> Result<String, String> {
+        let mut expanded = String::new();
+        for &(ref text, expandable) in &self.segments {
+            if expandable {
+                expanded.push_str(&expand_vars(text)?);
+            } else {
+                expanded.push_str(text);
+            }
+        }
+        if !self.tilde {
+            return Ok(expanded);
+        }
+        // Only `~` and `~/...` are expanded, not `~user`.
+        let home = env::var("HOME").ok();
+        match home {
+            Some(ref home) if expanded.is_empty() || expanded.starts_with('/') => {
+                Ok(format!("{}{}", home, expanded))
+            }
+            _ => Ok(format!("~{}", expanded)),
+        }
+    }
+}
+
+/// Replace the `$VAR` and `${VAR}` in a string with the value of the
+/// environment variables.
+fn expand_vars(text: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let (name, len) = if rest.starts_with('{') {
+            match rest.find('}') {
+                Some(end) => (&rest[1..end], end + 1),
+                None => return Err(rest.to_string()),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        };
+        if name.is_empty() {
+            // A `$` that doesn't start a variable name is kept as is.
+            expanded.push('$');
+            continue;
+        }
+        match env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => return Err(name.to_string()),
+        }
+        rest = &rest[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Split the input of the command prompt into commands, on the `;`
+/// that are neither quoted nor escaped. Quotes are not checked here:
+/// an unterminated quote is reported when the command is tokenized.
+pub fn split_commands(input: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, ';') => {
+                commands.push(&input[start..i]);
+                start = i + 1;
+            }
+            (None, _) => {}
+        }
+    }
+    commands.push(&input[start..]);
+    commands
+}
+
+/// Split the input of the command prompt into words.
+pub fn tokenize(input: &str) -> Result<Vec<Word>, ParseCommandError> {
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(Word::default);
+                // Make sure an empty quoted string still makes a word.
+                word.segments.push((String::new(), false));
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c, false),
+                        None => return Err(ParseCommandError::UnterminatedQuote),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(Word::default);
+                word.segments.push((String::new(), true));
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ '"') | Some(c @ '\\') | Some(c @ '$') => word.push(c, false),
+                            Some(c) => {
+                                word.push('\\', true);
+                                word.push(c, true);
+                            }
+                            None => return Err(ParseCommandError::UnterminatedQuote),
+                        },
+                        Some(c) => word.push(c, true),
+                        None => return Err(ParseCommandError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\\' => {
+                // A trailing backslash is kept as is.
+                let c = chars.next().unwrap_or('\\');
+                word.get_or_insert_with(Word::default).push(c, false);
+            }
+            '~' if word.is_none() => {
+                word = Some(Word {
+                    tilde: true,
+                    segments: Vec::new(),
+                })
+            }
+            c => word.get_or_insert_with(Word::default).push(c, true),
+        }
+    }
+    if let Some(word) = word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Quote a word if needed, so that `tokenize` gives it back unchanged.
+pub fn quote(text: &str) -> String {
+    let special = |c: char| c.is_whitespace() || "'\"\\$~;".contains(c);
+    if !text.is_empty() && !text.contains(special) {
+        return text.to_string();
+    }
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Escape the characters of a word that `tokenize` would interpret,
+/// with backslashes.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || "'\"\\$~;".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<String> {
+        match tokenize(input) {
+            Ok(words) => words.iter().map(Word::text).collect(),
+            Err(e) => panic!("cannot tokenize {:?}: {}", input, e),
+        }
+    }
+
+    fn expanded(input: &str) -> Result<Vec<String>, String> {
+        tokenize(input)
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(Word::expand)
+            .collect()
+    }
+
+    fn is_unterminated(input: &str) -> bool {
+        matches!(tokenize(input), Err(ParseCommandError::UnterminatedQuote))
+    }
+
+    #[test]
+    fn whitespace_separates_words() {
+        assert_eq!(words("open foo.txt"), vec!["open", "foo.txt"]);
+        assert_eq!(words("  open \t foo.txt  "), vec!["open", "foo.txt"]);
+        assert!(words("").is_empty());
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(words("open 'my notes.txt'"), vec!["open", "my notes.txt"]);
+        assert_eq!(words("open \"my notes.txt\""), vec!["open", "my notes.txt"]);
+        // Quoted parts and unquoted ones make a single word.
+        assert_eq!(words("a'b c'd\"e f\""), vec!["ab cde f"]);
+        // Quotes keep the other kind of quotes, and backslashes.
+        assert_eq!(words("'say \"hi\"' \"it's\""), vec!["say \"hi\"", "it's"]);
+        assert_eq!(words("'a\\b'"), vec!["a\\b"]);
+        // Only \", \\ and \$ are escapes within double quotes.
+        assert_eq!(words("\"\\\" \\\\ \\$ \\n\""), vec!["\" \\ $ \\n"]);
+    }
+
+    #[test]
+    fn empty_quotes_make_a_word() {
+        assert_eq!(words("search ''"), vec!["search", ""]);
+        assert_eq!(words("search \"\" c"), vec!["search", "", "c"]);
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert!(is_unterminated("open 'foo"));
+        assert!(is_unterminated("open \"foo"));
+        assert!(is_unterminated("open \"foo\\\""));
+        assert!(is_unterminated("open \"foo\\"));
+        assert!(is_unterminated("'"));
+    }
+
+    #[test]
+    fn backslashes() {
+        assert_eq!(words("open my\\ notes.txt"), vec!["open", "my notes.txt"]);
+        assert_eq!(words("a\\'b \\\\"), vec!["a'b", "\\"]);
+        // A trailing backslash is kept as is.
+        assert_eq!(words("foo\\"), vec!["foo\\"]);
+        assert_eq!(words("\\"), vec!["\\"]);
+    }
+
+    #[test]
+    fn tilde() {
+        let home = match env::var("HOME") {
+            Ok(home) => home,
+            Err(_) => return,
+        };
+        assert_eq!(expanded("~"), Ok(vec![home.clone()]));
+        assert_eq!(
+            expanded("~/notes.txt"),
+            Ok(vec![format!("{}/notes.txt", home)])
+        );
+        assert_eq!(
+            expanded("~\"/my notes\""),
+            Ok(vec![format!("{}/my notes", home)])
+        );
+        // Only a leading unquoted `~` is expanded, and not `~user`.
+        assert_eq!(expanded("~user/a"), Ok(vec!["~user/a".to_string()]));
+        assert_eq!(expanded("a~"), Ok(vec!["a~".to_string()]));
+        assert_eq!(
+            expanded("'~'/a \\~/b"),
+            Ok(vec!["~/a".into(), "~/b".into()])
+        );
+        // The text of a word keeps its tilde.
+        assert_eq!(words("~/a"), vec!["~/a"]);
+    }
+
+    #[test]
+    fn variables() {
+        env::set_var("XI_TERM_TEST_DIR", "/tmp/x y");
+        env::remove_var("XI_TERM_TEST_UNDEFINED");
+        assert_eq!(
+            expanded("$XI_TERM_TEST_DIR/a"),
+            Ok(vec!["/tmp/x y/a".into()])
+        );
+        assert_eq!(
+            expanded("${XI_TERM_TEST_DIR}b \"$XI_TERM_TEST_DIR\""),
+            Ok(vec!["/tmp/x yb".into(), "/tmp/x y".into()])
+        );
+        // Single quotes and escapes prevent the expansion.
+        assert_eq!(
+            expanded("'$XI_TERM_TEST_DIR' \\$XI_TERM_TEST_DIR \"\\$XI_TERM_TEST_DIR\""),
+            Ok(vec!["$XI_TERM_TEST_DIR".to_string(); 3])
+        );
+        // A `$` that doesn't start a name is kept.
+        assert_eq!(expanded("a$ $/b"), Ok(vec!["a$".into(), "$/b".into()]));
+        assert_eq!(
+            expanded("$XI_TERM_TEST_UNDEFINED"),
+            Err("XI_TERM_TEST_UNDEFINED".to_string())
+        );
+        assert_eq!(
+            expanded("${XI_TERM_TEST_DIR"),
+            Err("{XI_TERM_TEST_DIR".to_string())
+        );
+        // Expansion only happens when asked for.
+        assert_eq!(words("$XI_TERM_TEST_DIR"), vec!["$XI_TERM_TEST_DIR"]);
+    }
+
+    #[test]
+    fn quote_and_escape_round_trip() {
+        for text in &[
+            "plain",
+            "",
+            "my notes.txt",
+            "it's",
+            "a\"b",
+            "\\",
+            "$HOME",
+            "~",
+            "a;b",
+        ] {
+            assert_eq!(
+                words(&quote(text)),
+                vec![text.to_string()],
+                "quote({:?})",
+                text
+            );
+            assert_eq!(expanded(&quote(text)), Ok(vec![text.to_string()]));
+            if !text.is_empty() {
+                assert_eq!(
+                    words(&escape(text)),
+                    vec![text.to_string()],
+                    "escape({:?})",
+                    text
+                );
+            }
+        }
+        assert_eq!(quote("plain"), "plain");
+    }
+
+    #[test]
+    fn split_on_semicolons() {
+        assert_eq!(split_commands("s; bn"), vec!["s", " bn"]);
+        assert_eq!(split_commands("s;;bn;"), vec!["s", "", "bn", ""]);
+        assert_eq!(split_commands("? 'a;b'; sn"), vec!["? 'a;b'", " sn"]);
+        assert_eq!(split_commands("? \"a;b\";sn"), vec!["? \"a;b\"", "sn"]);
+        assert_eq!(split_commands(r"? a\;b;sn"), vec![r"? a\;b", "sn"]);
+        // An unterminated quote keeps the rest of the input.
+        assert_eq!(split_commands("? 'a;b"), vec!["? 'a;b"]);
+    }
+}
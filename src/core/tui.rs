@@ -1,16 +1,20 @@
 use std::io::{self, Write};
+use std::path::Path;
 
 use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::sync::oneshot::{self, Receiver, Sender};
 use futures::{Async, Future, Poll, Sink, Stream};
 
 use termion::event::{Event, Key};
-use xrl::{Client, Frontend, FrontendBuilder, MeasureWidth, XiNotification};
+use xrl::{Client, Frontend, FrontendBuilder, MeasureWidth, ViewId, XiNotification};
 
 use failure::Error;
 
-use core::{Command, Terminal, TerminalEvent};
-use widgets::{CommandPrompt, Editor};
+use core::{
+    help, Clipboard, Command, CompletionContext, History, MacroStep, Macros, Terminal,
+    TerminalEvent, MAX_COUNT,
+};
+use widgets::{CommandPrompt, Editor, Message};
 
 pub struct Tui {
     /// The editor holds the text buffers (named "views" in xi
@@ -30,23 +34,75 @@ pub struct Tui {
     /// Whether the editor is shutting down.
     exit: bool,
 
+    /// Whether to open an empty buffer when the last one is closed,
+    /// rather than quitting.
+    keep_last_buffer: bool,
+
+    /// Whether quitting was just refused because of unsaved changes.
+    /// Pressing Ctrl-c right after forces it.
+    quit_refused: bool,
+
+    /// The buffer saved by `write-quit`: we quit once the core has
+    /// saved it.
+    quit_after_save: Option<ViewId>,
+
+    /// The keyboard macros.
+    macros: Macros,
+
+    /// The commands entered in the command prompt.
+    history: History,
+
+    /// The queries entered in the incremental search prompt.
+    search_history: History,
+
+    /// Repeat count typed with Alt+digits, applied to the next key
+    /// binding.
+    count: Option<usize>,
+
     /// Stream of messages from Xi core.
     core_events: UnboundedReceiver<CoreEvent>,
 }
 
 impl Tui {
     /// Create a new Tui instance.
-    pub fn new(client: Client, events: UnboundedReceiver<CoreEvent>) -> Result<Self, Error> {
+    pub fn new(
+        client: Client,
+        events: UnboundedReceiver<CoreEvent>,
+        clipboard: Clipboard,
+        macros: Macros,
+        history: History,
+        record: Option<&Path>,
+        replay: Option<&Path>,
+    ) -> Result<Self, Error> {
         Ok(Tui {
-            terminal: Terminal::new()?,
+            terminal: Terminal::new(record, replay)?,
             exit: false,
+            quit_refused: false,
+            quit_after_save: None,
+            keep_last_buffer: false,
+            macros,
+            history,
+            search_history: History::new(None, 0),
+            count: None,
             term_size: (0, 0),
-            editor: Editor::new(client),
+            editor: Editor::new(client, clipboard),
             prompt: None,
             core_events: events,
         })
     }
 
+    /// Open an empty buffer when the last one is closed, instead of
+    /// quitting.
+    pub fn set_keep_last_buffer(&mut self, keep: bool) {
+        self.keep_last_buffer = keep;
+    }
+
+    /// Set the history of the incremental search prompt. Without one,
+    /// queries are not remembered.
+    pub fn set_search_history(&mut self, history: History) {
+        self.search_history = history;
+    }
+
     fn handle_resize(&mut self, size: (u16, u16)) {
         self.term_size = size;
         self.editor.handle_resize(size);
@@ -57,67 +113,307 @@ impl Tui {
             Command::Cancel => {
                 self.prompt = None;
             }
-            Command::Quit => self.exit = true,
-            Command::Save(view) => self.editor.save(view),
+            Command::Quit => self.quit(None),
+            Command::ForceQuit => self.exit = true,
+            Command::Save(view, file) => {
+                if !self.editor.save(view, file) {
+                    self.ask("save ");
+                }
+            }
+            Command::SaveAll => self.editor.save_all(),
+            Command::WriteQuit(file) => {
+                if self.editor.save(None, file) {
+                    self.quit_after_save = Some(self.editor.current_view);
+                } else {
+                    self.ask("write-quit ");
+                }
+            }
             Command::Back => self.editor.back(),
             Command::Delete => self.editor.delete(),
             Command::Open(file) => self.editor.new_view(file),
             Command::SetTheme(theme) => self.editor.set_theme(&theme),
+            Command::SetLanguage(language) => self.editor.set_language(language.as_deref()),
+            Command::SwitchBuffer(name) => self.editor.switch_buffer(&name),
+            Command::Close(buffer, force) => {
+                if self.editor.close(buffer, force) && self.editor.views.is_empty() {
+                    if self.keep_last_buffer {
+                        self.editor.new_view(None);
+                    } else {
+                        self.exit = true;
+                    }
+                }
+            }
+            Command::ListBuffers => {
+                self.editor.message = Some(Message::Info(self.editor.list_buffers()));
+            }
             Command::NextBuffer => self.editor.next_buffer(),
             Command::PrevBuffer => self.editor.prev_buffer(),
             Command::MoveLeft => self.editor.move_left(),
             Command::MoveRight => self.editor.move_right(),
             Command::MoveUp => self.editor.move_up(),
             Command::MoveDown => self.editor.move_down(),
+            Command::MoveWordLeft => self.editor.move_word_left(),
+            Command::MoveWordRight => self.editor.move_word_right(),
+            Command::ParagraphUp => self.editor.paragraph_up(),
+            Command::ParagraphDown => self.editor.paragraph_down(),
+            Command::DocumentStart => self.editor.document_start(),
+            Command::DocumentEnd => self.editor.document_end(),
+            Command::Goto(line, column) => self.editor.goto(line, column),
+            Command::SelectLeft => self.editor.select_left(),
+            Command::SelectRight => self.editor.select_right(),
+            Command::SelectUp => self.editor.select_up(),
+            Command::SelectDown => self.editor.select_down(),
+            Command::SelectWordLeft => self.editor.select_word_left(),
+            Command::SelectWordRight => self.editor.select_word_right(),
+            Command::SelectHome => self.editor.select_home(),
+            Command::SelectEnd => self.editor.select_end(),
+            Command::SelectPageUp => self.editor.select_page_up(),
+            Command::SelectPageDown => self.editor.select_page_down(),
+            Command::SelectDocumentStart => self.editor.select_document_start(),
+            Command::SelectDocumentEnd => self.editor.select_document_end(),
             Command::PageDown => self.editor.page_down(),
             Command::PageUp => self.editor.page_up(),
+            Command::AddCursorAbove => self.editor.add_cursor_above(),
+            Command::AddCursorBelow => self.editor.add_cursor_below(),
+            Command::SelectNextOccurrence => self.editor.select_next_occurrence(),
+            Command::SelectAllOccurrences => self.editor.select_all_occurrences(),
+            Command::CollapseSelections => self.editor.collapse_selections(),
             Command::ToggleLineNumbers => self.editor.toggle_line_numbers(),
+            Command::Copy => self.editor.copy(),
+            Command::Cut => self.editor.cut(),
+            Command::Paste => self.editor.paste_clipboard(),
+            Command::Undo => self.editor.undo(),
+            Command::Redo => self.editor.redo(),
+            Command::Repeat(count, cmd) => {
+                for _ in 0..count {
+                    self.run_command((*cmd).clone());
+                }
+            }
+            Command::Record(register) => {
+                let message = format!("recording macro in register {}", register);
+                self.macros.start_recording(register);
+                self.editor.message = Some(Message::Info(message));
+            }
+            Command::StopRecording => {
+                self.editor.message = Some(match self.macros.stop_recording() {
+                    Some(register) => Message::Info(format!("recorded macro {}", register)),
+                    None => Message::Error("not recording a macro".into()),
+                });
+            }
+            Command::Play(register, count) => self.play_macro(&register, count),
+            Command::Help(cmd) => {
+                self.editor.message = Some(Message::Info(help(cmd.as_deref())));
+            }
+            Command::Search(query) => self.editor.search(&query),
+            Command::SearchNext => self.editor.search_next(),
+            Command::SearchPrev => self.editor.search_prev(),
+            Command::NoHighlight => self.editor.hide_matches(),
+            Command::Set(setting, global) => self.editor.set_option(setting, global),
+            Command::ListPlugins => {
+                self.editor.message = Some(Message::Info(self.editor.list_plugins()));
+            }
+            Command::Plugin(action, name) => self.editor.toggle_plugin(action, &name),
+            Command::PluginCmd(name) => self.editor.plugin_command(&name),
+            Command::Replace(query, replacement, all) => {
+                self.editor.replace(&query, &replacement, all)
+            }
+            Command::DeleteLines => self.editor.delete(),
+            Command::Sort => self.editor.sort_lines(),
+            Command::Indent => self.editor.indent(),
+            Command::Outdent => self.editor.outdent(),
+            Command::Ranged(range, cmd) => {
+                if self.editor.select_lines(range) {
+                    self.run_command(*cmd);
+                }
+            }
+            Command::Sequence(cmds) => {
+                for cmd in cmds {
+                    self.run_command(cmd);
+                }
+            }
         }
     }
 
+    /// Quit, unless some buffers (other than the one of the given view)
+    /// have unsaved changes.
+    fn quit(&mut self, except: Option<ViewId>) {
+        let dirty = self.editor.dirty_buffers(except);
+        if dirty.is_empty() {
+            self.exit = true;
+            return;
+        }
+        let message = format!(
+            "unsaved changes in {}: save them, or use force-quit (q!) or press ^c again to quit anyway",
+            dirty.join(", ")
+        );
+        self.editor.message = Some(Message::Error(message));
+        self.quit_refused = true;
+    }
+
+    /// Open the command prompt with the start of a command, for the
+    /// user to complete it. This is used to ask for a missing argument,
+    /// e.g. the file name of a buffer that doesn't have one yet.
+    fn ask(&mut self, cmd: &str) {
+        let mut prompt = CommandPrompt::new(self.history.entries().to_vec());
+        prompt.paste(cmd);
+        self.prompt = Some(prompt);
+    }
+
+    fn play_macro(&mut self, register: &str, count: usize) {
+        let steps = match self.macros.start_playing(register) {
+            Ok(steps) => steps,
+            Err(reason) => {
+                let message = format!("cannot play macro {}: {}", register, reason);
+                self.editor.message = Some(Message::Error(message));
+                return;
+            }
+        };
+        for _ in 0..count {
+            for step in &steps {
+                match step.clone() {
+                    MacroStep::Command(cmd) => self.run_command(cmd),
+                    MacroStep::Input(event, count) => self.editor.handle_input(event, count),
+                    MacroStep::Paste(text) => self.editor.paste(&text),
+                }
+            }
+        }
+        self.macros.stop_playing();
+    }
+
     /// Global keybindings can be parsed here
     fn handle_input(&mut self, event: Event) {
         debug!("handling input {:?}", event);
+        let quit_refused = self.quit_refused;
+        self.quit_refused = false;
         match event {
-            Event::Key(Key::Ctrl('c')) => self.exit = true,
+            Event::Key(Key::Ctrl('c')) if quit_refused => self.exit = true,
+            Event::Key(Key::Ctrl('c')) => self.quit(None),
             Event::Key(Key::Alt('x')) => {
                 if let Some(ref mut prompt) = self.prompt {
-                    match prompt.handle_input(&event) {
+                    match prompt.handle_input(&event, &[]) {
                         Ok(None) => {}
                         Ok(Some(_)) => unreachable!(),
                         Err(_) => unreachable!(),
                     }
                 } else {
-                    self.prompt = Some(CommandPrompt::default());
+                    self.count = None;
+                    self.editor.message = None;
+                    self.prompt = Some(CommandPrompt::new(self.history.entries().to_vec()));
                 }
             }
+            Event::Key(Key::Ctrl('f')) if self.prompt.is_none() => {
+                self.count = None;
+                self.editor.message = None;
+                self.editor.start_search();
+                self.prompt = Some(CommandPrompt::find(self.search_history.entries().to_vec()));
+            }
+            // Saving is handled here, so that the file name can be asked
+            // for if the buffer doesn't have one.
+            Event::Key(Key::Ctrl('w')) if self.prompt.is_none() => {
+                self.count = None;
+                let cmd = Command::Save(None, None);
+                self.macros.record(MacroStep::Command(cmd.clone()));
+                self.run_command(cmd);
+            }
+            Event::Key(Key::Char('\t')) if self.prompt.is_some() => self.complete(false),
+            // Shift-Tab
+            Event::Unsupported(ref seq) if seq == b"\x1b[Z" && self.prompt.is_some() => {
+                self.complete(true)
+            }
+            Event::Key(Key::Alt(c)) if c.is_ascii_digit() && self.prompt.is_none() => {
+                let digit = c.to_digit(10).unwrap() as usize;
+                let count = self.count.unwrap_or(0) * 10 + digit;
+                self.count = Some(count.min(MAX_COUNT));
+            }
             event => {
                 // No command prompt is active, process the event normally.
                 if self.prompt.is_none() {
-                    self.editor.handle_input(event);
+                    let count = self.count.take().unwrap_or(1).max(1);
+                    self.macros.record(MacroStep::Input(event.clone(), count));
+                    self.editor.handle_input(event, count);
                     return;
                 }
 
                 // A command prompt is active.
                 let mut prompt = self.prompt.take().unwrap();
-                match prompt.handle_input(&event) {
+                let text = prompt.text().to_string();
+                match prompt.handle_input(&event, &self.editor.plugin_commands()) {
                     Ok(None) => {
+                        if prompt.is_find() && prompt.text() != text {
+                            self.editor.update_search(prompt.text());
+                        }
                         self.prompt = Some(prompt);
                     }
-                    Ok(Some(cmd)) => self.run_command(cmd),
+                    Ok(Some(cmd)) if prompt.is_find() => {
+                        match cmd {
+                            Command::Cancel => self.editor.end_search(false),
+                            ref cmd => {
+                                self.search_history.add(prompt.text());
+                                self.editor.end_search(true);
+                                self.macros.record(MacroStep::Command(cmd.clone()));
+                            }
+                        }
+                        self.run_command(cmd)
+                    }
+                    Ok(Some(cmd)) => {
+                        self.history.add(prompt.text());
+                        // Only the command entered is recorded, not the
+                        // commands it runs (when repeated for instance).
+                        match cmd {
+                            Command::Cancel | Command::Record(_) | Command::StopRecording => {}
+                            ref cmd => self.macros.record(MacroStep::Command(cmd.clone())),
+                        }
+                        self.run_command(cmd)
+                    }
                     Err(err) => {
-                        error!("Failed to parse command: {:?}", err);
+                        // Keep the command so that it can be fixed.
+                        self.history.add(prompt.text());
+                        error!("Failed to parse command: {}", err);
+                        self.editor.message = Some(Message::Error(err.to_string()));
                     }
                 }
             }
         }
     }
 
-    fn render(&mut self) -> Result<(), Error> {
+    /// Complete the word before the cursor in the command prompt.
+    fn complete(&mut self, backward: bool) {
+        let ctx = CompletionContext {
+            themes: self.editor.themes.clone(),
+            languages: self.editor.languages.clone(),
+            buffers: self.editor.buffer_names(),
+            plugins: self.editor.plugin_names(),
+            plugin_commands: self.editor.plugin_commands(),
+        };
+        if let Some(ref mut prompt) = self.prompt {
+            prompt.complete(&ctx, backward);
+        }
+    }
+
+    /// Pasted text is inserted literally: into the command prompt if
+    /// it is open, otherwise into the current view as a single edit.
+    fn handle_paste(&mut self, text: &str) {
+        debug!("handling paste of {} bytes", text.len());
         if let Some(ref mut prompt) = self.prompt {
-            prompt.render(self.terminal.stdout(), self.term_size.1)?;
+            prompt.paste(text);
         } else {
-            self.editor.render(self.terminal.stdout())?;
+            self.macros.record(MacroStep::Paste(text.to_string()));
+            self.editor.paste(text);
+        }
+    }
+
+    fn render(&mut self) -> Result<(), Error> {
+        match self.prompt {
+            // The buffer stays visible during an incremental search, to
+            // show the matches.
+            Some(ref mut prompt) if prompt.is_find() => {
+                self.editor.render(self.terminal.stdout())?;
+                prompt.render(self.terminal.stdout(), self.term_size.1, self.term_size.0)?;
+            }
+            Some(ref mut prompt) => {
+                prompt.render(self.terminal.stdout(), self.term_size.1, self.term_size.0)?;
+            }
+            None => self.editor.render(self.terminal.stdout())?,
         }
         if let Err(e) = self.terminal.stdout().flush() {
             error!("failed to flush stdout: {}", e);
@@ -157,6 +453,7 @@ impl Tui {
                 Ok(Async::Ready(Some(event))) => match event {
                     TerminalEvent::Input(event) => self.handle_input(event),
                     TerminalEvent::Resize(event) => self.handle_resize(event),
+                    TerminalEvent::Paste(text) => self.handle_paste(&text),
                 },
                 Ok(Async::Ready(None)) => {
                     info!("The terminal exited normally. Shutting down the TUI");
@@ -177,6 +474,19 @@ impl Tui {
         }
     }
 
+    /// Quit once the buffer saved by `write-quit` is saved. If the
+    /// save failed, the alert of the core is shown and we don't quit.
+    fn poll_saved(&mut self) {
+        for (view_id, success) in self.editor.poll_synced() {
+            if self.quit_after_save == Some(view_id) {
+                self.quit_after_save = None;
+                if success {
+                    self.quit(None);
+                }
+            }
+        }
+    }
+
     fn poll_rpc(&mut self) {
         debug!("polling for RPC messages");
         loop {
@@ -225,6 +535,11 @@ impl Future for Tui {
             info!("exiting the TUI");
             return Ok(Async::Ready(()));
         }
+        self.poll_saved();
+        if self.exit {
+            info!("exiting the TUI");
+            return Ok(Async::Ready(()));
+        }
 
         debug!("done polling the TUI components");
         debug!("rendering");
@@ -5,6 +5,7 @@
 #[macro_use]
 extern crate clap;
 
+extern crate base64;
 extern crate failure;
 
 #[macro_use]
@@ -13,13 +14,18 @@ extern crate log4rs;
 
 extern crate futures;
 extern crate indexmap;
+#[macro_use]
+extern crate serde_json;
 extern crate termion;
 extern crate tokio;
+extern crate unicode_width;
 extern crate xdg;
 extern crate xrl;
 
 mod core;
 mod widgets;
+use std::path::{Path, PathBuf};
+
 use xdg::BaseDirectories;
 
 use failure::Error;
@@ -29,7 +35,9 @@ use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Logger, Root};
 use xrl::spawn;
 
-use core::{Command, Tui, TuiServiceBuilder};
+use core::{
+    commands_table, options_table, Clipboard, Command, History, Macros, Tui, TuiServiceBuilder,
+};
 
 fn configure_logs(logfile: &str) {
     let tui = FileAppender::builder().build(logfile).unwrap();
@@ -62,6 +70,18 @@ fn configure_logs(logfile: &str) {
     let _ = log4rs::init_config(config).unwrap();
 }
 
+/// Get the path of a file in the `xi-term` data directory, creating
+/// the directory if needed.
+fn data_file(name: &str) -> Option<PathBuf> {
+    BaseDirectories::with_prefix("xi-term")
+        .ok()
+        .and_then(|dirs| {
+            dirs.place_data_file(name)
+                .map_err(|e| error!("failed to create the data directory: {}", e))
+                .ok()
+        })
+}
+
 fn main() {
     if let Err(ref e) = run() {
         use std::io::Write;
@@ -86,9 +106,27 @@ fn run() -> Result<(), Error> {
         (about: "The Xi Editor")
         (@arg core: -c --core +takes_value "Specify binary to use for the backend")
         (@arg logfile: -l --logfile +takes_value "Log file location")
-        (@arg file: +required "File to edit"));
+        (@arg no_osc52: --("no-osc52") "Don't use OSC 52 escape sequences to set the system clipboard")
+        (@arg copy_cmd: --("copy-cmd") +takes_value "Shell command copied text is piped to, when OSC 52 is disabled")
+        (@arg paste_cmd: --("paste-cmd") +takes_value "Shell command that prints the system clipboard")
+        (@arg record: --record +takes_value "Record the terminal events to a file")
+        (@arg replay: --replay +takes_value conflicts_with[record] "Replay the terminal events recorded in a file")
+        (@arg history_size: --("history-size") +takes_value "Number of commands to keep in the command prompt history (default: 1000)")
+        (@arg save_macros: --("save-macros") "Save keyboard macros so that they are available in later sessions")
+        (@arg keep_last_buffer: --("keep-last-buffer") "Open an empty buffer when the last one is closed, instead of quitting")
+        (@arg list_commands: --("list-commands") "Print the table of the commands, in Markdown, and exit")
+        (@arg list_options: --("list-options") "Print the table of the options of `set`, in Markdown, and exit")
+        (@arg file: required_unless_one(&["list_commands", "list_options"]) "File to edit"));
 
     let matches = xi.get_matches();
+    if matches.is_present("list_commands") {
+        print!("{}", commands_table());
+        return Ok(());
+    }
+    if matches.is_present("list_options") {
+        print!("{}", options_table());
+        return Ok(());
+    }
     if let Some(logfile) = matches.value_of("logfile") {
         configure_logs(logfile);
     }
@@ -122,8 +160,36 @@ fn run() -> Result<(), Error> {
                 .map_err(|e| error!("failed to send \"client_started\" {:?}", e))
                 .and_then(move |_| {
                     info!("initializing the TUI");
-                    let mut tui = Tui::new(client_clone, core_events_rx)
-                        .expect("failed to initialize the TUI");
+                    let clipboard = Clipboard::new(
+                        !matches.is_present("no_osc52"),
+                        matches.value_of("copy_cmd").map(ToString::to_string),
+                        matches.value_of("paste_cmd").map(ToString::to_string),
+                    );
+                    let macros_file = if matches.is_present("save_macros") {
+                        data_file("macros.json")
+                    } else {
+                        None
+                    };
+                    let history_size = matches
+                        .value_of("history_size")
+                        .and_then(|size| {
+                            size.parse()
+                                .map_err(|e| error!("invalid history size {}: {}", size, e))
+                                .ok()
+                        })
+                        .unwrap_or(1000);
+                    let mut tui = Tui::new(
+                        client_clone,
+                        core_events_rx,
+                        clipboard,
+                        Macros::new(macros_file),
+                        History::new(data_file("history"), history_size),
+                        matches.value_of("record").map(Path::new),
+                        matches.value_of("replay").map(Path::new),
+                    )
+                    .expect("failed to initialize the TUI");
+                    tui.set_keep_last_buffer(matches.is_present("keep_last_buffer"));
+                    tui.set_search_history(History::new(data_file("search-history"), history_size));
                     tui.run_command(Command::Open(
                         matches.value_of("file").map(ToString::to_string),
                     ));
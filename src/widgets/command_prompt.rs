@@ -6,23 +6,106 @@ use std::io::Error;
 use std::io::Write;
 use termion::event::{Event, Key};
 
-use core::{Command, ParseCommandError};
+use core::{complete, Command, CompletionContext, ParseCommandError, SearchQuery};
 use termion::clear::CurrentLine as ClearLine;
 use termion::cursor::Goto;
-
-use std::str::FromStr;
+use termion::style::{Invert, NoInvert};
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Debug, Default)]
 pub struct CommandPrompt {
+    /// Position of the cursor in `chars`, in bytes. It is always at the
+    /// boundary of a character.
     dex: usize,
     chars: String,
+    /// The completions being cycled through, if any.
+    completion: Option<Completion>,
+    /// The previous commands, from the oldest to the most recent.
+    history: Vec<String>,
+    /// Position in the history, while browsing it with Up and Down.
+    history_index: Option<usize>,
+    /// What was typed before browsing the history. Only the commands
+    /// starting with it are shown.
+    history_prefix: String,
+    /// The reverse incremental search started with Ctrl-R, if any.
+    search: Option<Search>,
+    /// Whether the prompt searches the current buffer as the user
+    /// types, rather than running a command.
+    find: bool,
+}
+
+/// State of a reverse incremental search in the history.
+#[derive(Debug, Default)]
+struct Search {
+    query: String,
+    /// Position in the history of the current match.
+    index: Option<usize>,
+    /// Whether the last search found nothing.
+    failing: bool,
+    /// What was typed before the search, restored if it is aborted.
+    original: String,
+}
+
+/// Completions of the word before the cursor.
+#[derive(Debug)]
+struct Completion {
+    /// Where the completed word starts.
+    start: usize,
+    candidates: Vec<String>,
+    /// The candidate currently inserted.
+    index: usize,
 }
 
 impl CommandPrompt {
-    /// Process a terminal event for the command prompt.
-    pub fn handle_input(&mut self, input: &Event) -> Result<Option<Command>, ParseCommandError> {
+    /// Create a prompt that can recall the given commands.
+    pub fn new(history: Vec<String>) -> Self {
+        CommandPrompt {
+            history,
+            ..Default::default()
+        }
+    }
+
+    /// Create a prompt for an incremental search in the current buffer,
+    /// that can recall the given queries.
+    pub fn find(history: Vec<String>) -> Self {
+        CommandPrompt {
+            history,
+            find: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_find(&self) -> bool {
+        self.find
+    }
+
+    /// The text typed in the prompt.
+    pub fn text(&self) -> &str {
+        &self.chars
+    }
+
+    /// Process a terminal event for the command prompt. The commands
+    /// provided by the plugins of the current buffer are given so that
+    /// they can be run.
+    pub fn handle_input(
+        &mut self,
+        input: &Event,
+        plugin_commands: &[String],
+    ) -> Result<Option<Command>, ParseCommandError> {
+        self.completion = None;
+        if self.search.is_some() && self.handle_search(input) {
+            return Ok(None);
+        }
         match input {
-            Event::Key(Key::Char('\n')) => self.finalize(),
+            Event::Key(Key::Up) | Event::Key(Key::Down) => {}
+            _ => self.history_index = None,
+        }
+        match input {
+            Event::Key(Key::Up) => Ok(self.history_prev()),
+            Event::Key(Key::Down) => Ok(self.history_next()),
+            Event::Key(Key::Ctrl('r')) => Ok(self.start_search()),
+            Event::Key(Key::Char('\n')) => self.finalize(plugin_commands),
+            Event::Key(Key::Esc) if self.find => Ok(Some(Command::Cancel)),
             Event::Key(Key::Backspace) | Event::Key(Key::Ctrl('h')) => Ok(self.back()),
             Event::Key(Key::Delete) => Ok(self.delete()),
             Event::Key(Key::Left) => Ok(self.left()),
@@ -33,15 +116,15 @@ impl CommandPrompt {
     }
 
     fn left(&mut self) -> Option<Command> {
-        if self.dex > 0 {
-            self.dex -= 1;
+        if let Some(c) = self.chars[..self.dex].chars().next_back() {
+            self.dex -= c.len_utf8();
         }
         None
     }
 
     fn right(&mut self) -> Option<Command> {
-        if self.dex < self.chars.len() {
-            self.dex += 1;
+        if let Some(c) = self.chars[self.dex..].chars().next() {
+            self.dex += c.len_utf8();
         }
         None
     }
@@ -54,38 +137,362 @@ impl CommandPrompt {
     }
 
     fn back(&mut self) -> Option<Command> {
-        if !self.chars.is_empty() {
-            self.dex -= 1;
+        if self.chars.is_empty() {
+            return Some(Command::Cancel);
+        }
+        if self.dex > 0 {
+            self.left();
             self.chars.remove(self.dex);
-            None
-        } else {
-            Some(Command::Cancel)
         }
+        None
     }
 
     /// Gets called when any character is pressed.
     fn new_key(&mut self, chr: char) -> Option<Command> {
         self.chars.insert(self.dex, chr);
-        self.dex += 1;
+        self.dex += chr.len_utf8();
+        None
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.dex = text.len();
+        self.chars = text;
+    }
+
+    /// Show the previous command starting with what was typed.
+    fn history_prev(&mut self) -> Option<Command> {
+        if self.history_index.is_none() {
+            self.history_prefix = self.chars.clone();
+        }
+        let end = self.history_index.unwrap_or(self.history.len());
+        let prefix = &self.history_prefix;
+        if let Some(i) = self.history[..end]
+            .iter()
+            .rposition(|entry| entry.starts_with(prefix.as_str()))
+        {
+            self.history_index = Some(i);
+            self.set_text(self.history[i].clone());
+        }
+        None
+    }
+
+    /// Show the next command starting with what was typed, or what was
+    /// typed once we reach the end of the history.
+    fn history_next(&mut self) -> Option<Command> {
+        let start = match self.history_index {
+            Some(i) => i + 1,
+            None => return None,
+        };
+        let prefix = &self.history_prefix;
+        match self.history[start..]
+            .iter()
+            .position(|entry| entry.starts_with(prefix.as_str()))
+        {
+            Some(i) => {
+                self.history_index = Some(start + i);
+                self.set_text(self.history[start + i].clone());
+            }
+            None => {
+                self.history_index = None;
+                self.set_text(self.history_prefix.clone());
+            }
+        }
         None
     }
 
+    fn start_search(&mut self) -> Option<Command> {
+        self.search = Some(Search {
+            original: self.chars.clone(),
+            ..Default::default()
+        });
+        None
+    }
+
+    /// Find the most recent command containing the query, before the
+    /// given position in the history.
+    fn search_before(&mut self, end: usize) {
+        let found = match self.search {
+            Some(ref mut search) => {
+                let query = &search.query;
+                let found = self.history[..end]
+                    .iter()
+                    .rposition(|entry| entry.contains(query.as_str()));
+                search.failing = found.is_none();
+                if found.is_some() {
+                    search.index = found;
+                }
+                found
+            }
+            None => return,
+        };
+        if let Some(i) = found {
+            self.set_text(self.history[i].clone());
+        }
+    }
+
+    /// Handle a key during a reverse incremental search. Return `false`
+    /// if the key ends the search and should be processed normally:
+    /// Enter runs the command found, and keys like Left and Right edit
+    /// it.
+    fn handle_search(&mut self, input: &Event) -> bool {
+        let (query_len, index) = match self.search {
+            Some(ref search) => (search.query.len(), search.index),
+            None => return false,
+        };
+        match input {
+            Event::Key(Key::Ctrl('r')) => {
+                let end = index.unwrap_or(self.history.len());
+                self.search_before(end);
+            }
+            Event::Key(Key::Char('\n')) => {
+                self.search = None;
+                return false;
+            }
+            Event::Key(Key::Backspace) | Event::Key(Key::Ctrl('h')) => {
+                if query_len > 0 {
+                    if let Some(ref mut search) = self.search {
+                        search.query.pop();
+                        search.index = None;
+                    }
+                    let end = self.history.len();
+                    self.search_before(end);
+                }
+            }
+            Event::Key(Key::Esc) | Event::Key(Key::Ctrl('g')) => {
+                if let Some(search) = self.search.take() {
+                    self.set_text(search.original);
+                }
+            }
+            Event::Key(Key::Char(c)) => {
+                if let Some(ref mut search) = self.search {
+                    search.query.push(*c);
+                }
+                // The current match may still match the longer query.
+                let end = index.map(|i| i + 1).unwrap_or(self.history.len());
+                self.search_before(end);
+            }
+            _ => {
+                self.search = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Complete the word before the cursor, or if completions are
+    /// already shown, replace it with the next (or previous) one.
+    pub fn complete(&mut self, ctx: &CompletionContext, backward: bool) {
+        if self.find {
+            return;
+        }
+        self.search = None;
+        if let Some(ref mut completion) = self.completion {
+            let len = completion.candidates.len();
+            completion.index = if backward {
+                (completion.index + len - 1) % len
+            } else {
+                (completion.index + 1) % len
+            };
+            let candidate = &completion.candidates[completion.index];
+            self.chars
+                .replace_range(completion.start..self.dex, candidate);
+            self.dex = completion.start + candidate.len();
+            return;
+        }
+
+        let (start, candidates) = complete(&self.chars[..self.dex], ctx);
+        if candidates.is_empty() {
+            return;
+        }
+        self.chars.replace_range(start..self.dex, &candidates[0]);
+        self.dex = start + candidates[0].len();
+        if candidates.len() > 1 {
+            self.completion = Some(Completion {
+                start,
+                candidates,
+                index: 0,
+            });
+        }
+    }
+
+    /// Insert pasted text at the cursor, without interpreting it.
+    pub fn paste(&mut self, text: &str) {
+        self.completion = None;
+        self.chars.insert_str(self.dex, text);
+        self.dex += text.len();
+    }
+
     /// Gets called when return is pressed,
-    fn finalize(&mut self) -> Result<Option<Command>, ParseCommandError> {
-        Ok(Some(FromStr::from_str(&self.chars)?))
+    fn finalize(
+        &mut self,
+        plugin_commands: &[String],
+    ) -> Result<Option<Command>, ParseCommandError> {
+        if self.find && self.chars.is_empty() {
+            return Ok(Some(Command::Cancel));
+        }
+        if self.find {
+            return Ok(Some(Command::Search(SearchQuery::new(
+                self.chars.clone(),
+                "",
+            ))));
+        }
+        let cmd = Command::parse(&self.chars, |name| {
+            plugin_commands.iter().any(|cmd| cmd == name)
+        })?;
+        Ok(Some(cmd))
     }
 
-    pub fn render<W: Write>(&mut self, w: &mut W, row: u16) -> Result<(), Error> {
+    pub fn render<W: Write>(&mut self, w: &mut W, row: u16, width: u16) -> Result<(), Error> {
+        if let Some(ref search) = self.search {
+            let prompt = format!(
+                "({}reverse-i-search)`{}'",
+                if search.failing { "failing " } else { "" },
+                search.query
+            );
+            if let Err(err) = write!(
+                w,
+                "{}{}{}: {}{}",
+                Goto(1, row),
+                ClearLine,
+                prompt,
+                self.chars,
+                Goto(prompt.width() as u16, row)
+            ) {
+                error!("failed to render the history search: {:?}", err);
+            }
+            return Ok(());
+        }
+        if let Some(ref completion) = self.completion {
+            if row > 1 {
+                if let Err(err) = render_completions(w, completion, row - 1, width) {
+                    error!("failed to render completions: {:?}", err);
+                }
+            }
+        }
         if let Err(err) = write!(
             w,
-            "{}{}:{}{}",
+            "{}{}{}{}{}",
             Goto(1, row),
             ClearLine,
+            if self.find { '/' } else { ':' },
             self.chars,
-            Goto(self.dex as u16 + 2, row)
+            Goto(self.chars[..self.dex].width() as u16 + 2, row)
         ) {
             error!("faile to render status bar: {:?}", err);
         }
         Ok(())
     }
 }
+
+/// Show the candidates on the line above the prompt, with the current
+/// one highlighted. If they don't all fit, the first ones are dropped
+/// so that the current one is visible.
+fn render_completions<W: Write>(
+    w: &mut W,
+    completion: &Completion,
+    row: u16,
+    width: u16,
+) -> Result<(), Error> {
+    let width = usize::from(width);
+    let candidates = &completion.candidates;
+    let len = |c: &String| c.width() + 2;
+    let mut first = 0;
+    while first < completion.index
+        && candidates[first..=completion.index]
+            .iter()
+            .map(len)
+            .sum::<usize>()
+            > width
+    {
+        first += 1;
+    }
+
+    write!(w, "{}{}", Goto(1, row), ClearLine)?;
+    let mut used = 0;
+    for (i, candidate) in candidates.iter().enumerate().skip(first) {
+        used += len(candidate);
+        if used > width {
+            break;
+        }
+        if i == completion.index {
+            write!(w, "{}{}{}  ", Invert, candidate, NoInvert)?;
+        } else {
+            write!(w, "{}  ", candidate)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(prompt: &mut CommandPrompt, key: Key) {
+        prompt.handle_input(&Event::Key(key), &[]).unwrap();
+    }
+
+    #[test]
+    fn edit_multi_byte_history_entry() {
+        let mut prompt = CommandPrompt::new(vec!["search 'café'".into()]);
+        key(&mut prompt, Key::Up);
+        assert_eq!(prompt.text(), "search 'café'");
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Char('s'));
+        assert_eq!(prompt.text(), "search 'cafsé'");
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Backspace);
+        assert_eq!(prompt.text(), "search 'casé'");
+        key(&mut prompt, Key::Char('ü'));
+        key(&mut prompt, Key::Delete);
+        assert_eq!(prompt.text(), "search 'caüé'");
+        key(&mut prompt, Key::Right);
+        key(&mut prompt, Key::Right);
+        key(&mut prompt, Key::Char('!'));
+        assert_eq!(prompt.text(), "search 'caüé'!");
+    }
+
+    #[test]
+    fn edit_multi_byte_completion() {
+        let ctx = CompletionContext {
+            buffers: vec!["naïve.rs".into(), "naïveté.rs".into()],
+            ..Default::default()
+        };
+        let mut prompt = CommandPrompt::new(Vec::new());
+        prompt.paste("buffer na");
+        prompt.complete(&ctx, false);
+        assert_eq!(prompt.text(), "buffer naïve.rs");
+        prompt.complete(&ctx, false);
+        assert_eq!(prompt.text(), "buffer naïveté.rs");
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Backspace);
+        assert_eq!(prompt.text(), "buffer naïvet.rs");
+        prompt.complete(&ctx, false);
+        assert_eq!(prompt.text(), "buffer naïveté.rs.rs");
+    }
+
+    #[test]
+    fn edit_multi_byte_search_match() {
+        let mut prompt = CommandPrompt::new(vec!["e 'à bientôt'".into(), "s".into()]);
+        key(&mut prompt, Key::Ctrl('r'));
+        key(&mut prompt, Key::Char('ô'));
+        assert_eq!(prompt.text(), "e 'à bientôt'");
+        // Left ends the search, and moves the cursor.
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Backspace);
+        assert_eq!(prompt.text(), "e 'à bientt'");
+    }
+
+    #[test]
+    fn back_at_the_start() {
+        let mut prompt = CommandPrompt::new(Vec::new());
+        prompt.paste("é");
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Left);
+        key(&mut prompt, Key::Backspace);
+        assert_eq!(prompt.text(), "é");
+    }
+}
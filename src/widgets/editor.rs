@@ -6,11 +6,17 @@ use futures::{Async, Future, Poll, Stream};
 
 use failure::Error;
 use indexmap::IndexMap;
-use termion::event::Event as TermionEvent;
-use xrl::{Client, ConfigChanged, ScrollTo, Style, Update, ViewId, XiNotification};
+use termion::event::{Event as TermionEvent, Key};
+use xrl::{
+    AvailablePlugins, Client, ConfigChanged, FindStatus, LanguageChanged, PluginStarted,
+    PluginStoped, ReplaceStatus, ScrollTo, Style, Update, UpdateCmds, ViewId, XiNotification,
+};
 
-use core::CoreEvent;
-use widgets::{View, ViewClient};
+use core::{
+    Clipboard, CoreEvent, OptionSpec, OptionValue, PluginAction, Range, SearchQuery, Setting,
+};
+use widgets::view::{Plugin, Synced};
+use widgets::{Message, View, ViewClient};
 
 /// The main interface to xi-core
 pub struct Editor {
@@ -23,6 +29,37 @@ pub struct Editor {
     /// sent, when they are received from the core.
     pub new_view_tx: UnboundedSender<(ViewId, Option<String>)>,
 
+    /// Channel from which the text copied or cut from the views is
+    /// received, when the core answers "copy" and "cut" requests.
+    pub clipboard_rx: UnboundedReceiver<String>,
+
+    /// Channel into which the text copied or cut from the views is
+    /// sent.
+    pub clipboard_tx: UnboundedSender<String>,
+
+    pub clipboard: Clipboard,
+
+    /// Channel from which the text read from the system clipboard is
+    /// received, once the paste command has run.
+    pub pasted_rx: UnboundedReceiver<String>,
+
+    /// Channel into which the text read from the system clipboard is
+    /// sent.
+    pub pasted_tx: UnboundedSender<String>,
+
+    /// Channel from which the notifications the core has handled are
+    /// received, with what to check then, e.g. whether there was
+    /// anything to undo. They are handled by `poll_synced`.
+    pub synced_rx: UnboundedReceiver<Synced>,
+
+    /// Channel into which the notifications the core has handled are
+    /// sent.
+    pub synced_tx: UnboundedSender<Synced>,
+
+    /// Number of alerts received from the core, to tell whether a save
+    /// failed.
+    alerts: u64,
+
     /// Store the events that we cannot process right away.
     ///
     /// Due to the asynchronous nature of the communication with the
@@ -44,24 +81,90 @@ pub struct Editor {
 
     pub size: (u16, u16),
     pub styles: HashMap<u64, Style>,
+
+    /// Channel from which the failures of the requests sent by the
+    /// views are received.
+    pub messages_rx: UnboundedReceiver<Message>,
+
+    /// Channel into which the failures of the requests sent by the
+    /// views are sent.
+    pub messages_tx: UnboundedSender<Message>,
+
+    /// Message displayed at the bottom of the screen until the next
+    /// input.
+    pub message: Option<Message>,
+
+    /// Names of the themes the core knows about.
+    pub themes: Vec<String>,
+
+    /// Names of the languages the core knows about.
+    pub languages: Vec<String>,
+
+    /// The replacement in progress, if any.
+    replace: Option<Replace>,
+
+    /// The options handled by xi-term that were set for all the
+    /// buffers, and are applied to the new ones.
+    view_options: Vec<(&'static OptionSpec, OptionValue)>,
+}
+
+/// A replacement of the matches of a search, either all at once, or
+/// one by one after asking the user.
+struct Replace {
+    view_id: ViewId,
+    /// Whether all the matches are replaced at once.
+    all: bool,
+    /// The replacement, once the core has acknowledged it.
+    replacement: Option<String>,
+    /// Number of matches left to review, once the core has found them.
+    remaining: Option<u64>,
+    replaced: u64,
 }
 
 /// Methods for general use.
 impl Editor {
-    pub fn new(client: Client) -> Editor {
+    pub fn new(client: Client, clipboard: Clipboard) -> Editor {
         let mut styles = HashMap::new();
         styles.insert(0, Default::default());
+        // The core uses the style 1 to highlight the matches of a search.
+        styles.insert(
+            1,
+            Style {
+                id: 1,
+                underline: Some(true),
+                ..Default::default()
+            },
+        );
         let (new_view_tx, new_view_rx) = mpsc::unbounded::<(ViewId, Option<String>)>();
+        let (clipboard_tx, clipboard_rx) = mpsc::unbounded::<String>();
+        let (pasted_tx, pasted_rx) = mpsc::unbounded::<String>();
+        let (synced_tx, synced_rx) = mpsc::unbounded::<Synced>();
+        let (messages_tx, messages_rx) = mpsc::unbounded::<Message>();
 
         Editor {
             new_view_rx,
             new_view_tx,
+            clipboard_rx,
+            clipboard_tx,
+            clipboard,
+            pasted_rx,
+            pasted_tx,
+            synced_rx,
+            synced_tx,
+            alerts: 0,
             delayed_events: Vec::new(),
             views: IndexMap::new(),
             current_view: ViewId(0),
             client,
             size: (0, 0),
             styles,
+            messages_rx,
+            messages_tx,
+            message: None,
+            themes: Vec::new(),
+            languages: Vec::new(),
+            replace: None,
+            view_options: Vec::new(),
         }
     }
 }
@@ -91,9 +194,13 @@ impl Future for Editor {
             match self.new_view_rx.poll() {
                 Ok(Async::Ready(Some((view_id, file_path)))) => {
                     info!("creating new view {:?}", view_id);
-                    let client = ViewClient::new(self.client.clone(), view_id);
+                    let client =
+                        ViewClient::new(self.client.clone(), view_id, self.messages_tx.clone());
                     let mut view = View::new(client, file_path);
-                    view.resize(self.size.1);
+                    view.resize(self.size.0, self.size.1);
+                    for &(spec, value) in &self.view_options {
+                        view.option_changed(spec, value);
+                    }
                     self.views.insert(view_id, view);
                     info!("switching to view {:?}", view_id);
                     self.current_view = view_id;
@@ -110,15 +217,126 @@ impl Future for Editor {
                 }
             }
         }
+
+        debug!("polling 'copy' and 'cut' responses");
+        loop {
+            match self.clipboard_rx.poll() {
+                Ok(Async::Ready(Some(text))) => self.clipboard.copy(text),
+                // We own one of the senders so this cannot happen
+                Ok(Async::Ready(None)) => unreachable!(),
+                Ok(Async::NotReady) => {
+                    debug!("no more 'copy' or 'cut' response");
+                    break;
+                }
+                Err(e) => {
+                    error!("Uknown channel error: {:?}", e);
+                    return Err(());
+                }
+            }
+        }
+
+        debug!("polling the text read from the system clipboard");
+        loop {
+            match self.pasted_rx.poll() {
+                Ok(Async::Ready(Some(text))) => self.paste_text(&text),
+                // We own one of the senders so this cannot happen
+                Ok(Async::Ready(None)) => unreachable!(),
+                Ok(Async::NotReady) => {
+                    debug!("no more pasted text");
+                    break;
+                }
+                Err(e) => {
+                    error!("Uknown channel error: {:?}", e);
+                    return Err(());
+                }
+            }
+        }
+
+        debug!("polling messages");
+        loop {
+            match self.messages_rx.poll() {
+                Ok(Async::Ready(Some(message))) => self.message = Some(message),
+                // We own one of the senders so this cannot happen
+                Ok(Async::Ready(None)) => unreachable!(),
+                Ok(Async::NotReady) => {
+                    debug!("no more message");
+                    break;
+                }
+                Err(e) => {
+                    error!("Uknown channel error: {:?}", e);
+                    return Err(());
+                }
+            }
+        }
         Ok(Async::NotReady)
     }
 }
 
 impl Editor {
-    /// Handle keyboard and mouse events
-    pub fn handle_input(&mut self, event: TermionEvent) {
-        if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.handle_input(event)
+    /// Check the notifications the core has handled: show the message
+    /// for the views that were not updated, e.g. "Nothing to undo", and
+    /// report the saves. Return the views the core tried to save, and
+    /// whether it did.
+    ///
+    /// They are sent after the core answered a request that follows
+    /// the notification, but the updates and alerts the notification
+    /// caused may not have been handled yet: this must be called after
+    /// handling the events from the core.
+    pub fn poll_synced(&mut self) -> Vec<(ViewId, bool)> {
+        let mut saved = Vec::new();
+        while let Ok(Async::Ready(Some(synced))) = self.synced_rx.poll() {
+            match synced {
+                Synced::Unchanged(view_id, updates, message) => {
+                    if self.views.get(&view_id).map(View::updates) == Some(updates) {
+                        self.message = Some(message);
+                    }
+                }
+                Synced::Saved(view_id, alerts) => {
+                    // The core sends an alert when it can't save a file,
+                    // which is already shown.
+                    let success = alerts == self.alerts;
+                    if let (true, Some(view)) = (success, self.views.get(&view_id)) {
+                        let file = view.file().unwrap_or_default();
+                        let message = format!("saved {} ({} lines)", file, view.line_count());
+                        self.message = Some(Message::Info(message));
+                    }
+                    saved.push((view_id, success));
+                }
+            }
+        }
+        saved
+    }
+
+    /// Handle keyboard and mouse events, repeated `count` times.
+    pub fn handle_input(&mut self, event: TermionEvent, count: usize) {
+        self.message = None;
+        if self.replace.is_some() {
+            self.handle_replace_input(&event);
+            return;
+        }
+        match event {
+            TermionEvent::Key(Key::Ctrl('z')) => {
+                for _ in 0..count {
+                    self.undo();
+                }
+            }
+            TermionEvent::Key(Key::Ctrl('y')) => {
+                for _ in 0..count {
+                    self.redo();
+                }
+            }
+            TermionEvent::Key(Key::Alt('c')) => self.copy(),
+            TermionEvent::Key(Key::Ctrl('x')) => self.cut(),
+            TermionEvent::Key(Key::Ctrl('v')) => {
+                for _ in 0..count {
+                    self.paste_clipboard();
+                }
+            }
+            event => {
+                if let Some(view) = self.views.get_mut(&self.current_view) {
+                    view.handle_input(event, count)
+                }
+            }
         }
     }
 
@@ -127,7 +345,7 @@ impl Editor {
         info!("setting new terminal size");
         self.size = size;
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.resize(size.1);
+            view.resize(size.0, size.1);
         } else {
             warn!("view {} not found", self.current_view);
         }
@@ -141,6 +359,23 @@ impl Editor {
                 XiNotification::DefStyle(style) => self.def_style(style),
                 XiNotification::ScrollTo(scroll_to) => self.scroll_to(scroll_to),
                 XiNotification::ConfigChanged(config) => self.config_changed(config),
+                XiNotification::AvailableThemes(themes) => self.themes = themes.themes,
+                XiNotification::AvailableLanguages(languages) => {
+                    self.languages = languages.languages
+                }
+                XiNotification::LanguageChanged(language) => self.language_changed(language),
+                XiNotification::FindStatus(status) => self.find_status(status),
+                XiNotification::ReplaceStatus(status) => self.replace_status(status),
+                XiNotification::AvailablePlugins(plugins) => self.available_plugins(plugins),
+                XiNotification::PluginStarted(plugin) => self.plugin_started(plugin),
+                XiNotification::PluginStoped(plugin) => self.plugin_stopped(plugin),
+                XiNotification::UpdateCmds(cmds) => self.update_cmds(cmds),
+                // The core reports the errors it can't report in a
+                // response this way, e.g. a failed save.
+                XiNotification::Alert(alert) => {
+                    self.alerts += 1;
+                    self.message = Some(Message::Error(alert.msg));
+                }
                 _ => info!("ignoring Xi core notification: {:?}", notification),
             },
             CoreEvent::MeasureWidth((_request, _result_tx)) => unimplemented!(),
@@ -165,6 +400,54 @@ impl Editor {
                 .delayed_events
                 .push(CoreEvent::Notify(XiNotification::ScrollTo(scroll_to))),
         }
+        self.report_match();
+    }
+
+    /// Handle a "find_status" notification from Xi core.
+    fn find_status(&mut self, status: FindStatus) {
+        if let Some(ref mut replace) = self.replace {
+            if replace.view_id == status.view_id && replace.remaining.is_none() {
+                let matches = status.queries.first().map_or(0, |query| query.matches);
+                replace.remaining = Some(matches);
+                if replace.all {
+                    replace.replaced = matches;
+                    replace.remaining = Some(0);
+                }
+            }
+        }
+        match self.views.get_mut(&status.view_id) {
+            Some(view) => view.set_find_status(status),
+            None => self
+                .delayed_events
+                .push(CoreEvent::Notify(XiNotification::FindStatus(status))),
+        }
+        self.report_match();
+    }
+
+    /// Handle a "replace_status" notification from Xi core.
+    fn replace_status(&mut self, status: ReplaceStatus) {
+        if let Some(ref mut replace) = self.replace {
+            if replace.view_id == status.view_id {
+                replace.replacement = Some(status.status.chars);
+            }
+        }
+        self.report_match();
+    }
+
+    /// Tell which match the cursor is on, if the user just moved to a
+    /// match in the current view.
+    fn report_match(&mut self) {
+        if self.replace.is_some() {
+            self.review_replace();
+            return;
+        }
+        let message = self
+            .views
+            .get(&self.current_view)
+            .and_then(|view| view.match_status());
+        if message.is_some() {
+            self.message = message;
+        }
     }
 
     /// Handle a "def_style" notification from Xi core.
@@ -182,10 +465,90 @@ impl Editor {
         }
     }
 
+    /// Handle a "language_changed" notification from Xi core. The user
+    /// is told about the language of the current buffer.
+    fn language_changed(&mut self, language: LanguageChanged) {
+        match self.views.get_mut(&language.view_id) {
+            Some(view) => {
+                if language.view_id == self.current_view {
+                    let message = format!("language: {}", language.language_id);
+                    self.message = Some(Message::Info(message));
+                }
+                view.language_changed(language.language_id);
+            }
+            None => self
+                .delayed_events
+                .push(CoreEvent::Notify(XiNotification::LanguageChanged(language))),
+        }
+    }
+
+    /// Handle an "available_plugins" notification from Xi core.
+    fn available_plugins(&mut self, plugins: AvailablePlugins) {
+        match self.views.get_mut(&plugins.view_id) {
+            Some(view) => view.set_plugins(
+                plugins
+                    .plugins
+                    .into_iter()
+                    .map(|plugin| Plugin {
+                        name: plugin.name,
+                        running: plugin.running,
+                    })
+                    .collect(),
+            ),
+            None => self
+                .delayed_events
+                .push(CoreEvent::Notify(XiNotification::AvailablePlugins(plugins))),
+        }
+    }
+
+    /// Handle a "plugin_started" notification from Xi core. The user is
+    /// only told about the plugins they started.
+    fn plugin_started(&mut self, plugin: PluginStarted) {
+        match self.views.get_mut(&plugin.view_id) {
+            Some(view) => {
+                if view.plugin_started(&plugin.plugin) {
+                    let message = format!("plugin {} started", plugin.plugin);
+                    self.message = Some(Message::Info(message));
+                }
+            }
+            None => self
+                .delayed_events
+                .push(CoreEvent::Notify(XiNotification::PluginStarted(plugin))),
+        }
+    }
+
+    /// Handle a "plugin_stopped" notification from Xi core. A plugin
+    /// that stops without the user asking for it has crashed.
+    fn plugin_stopped(&mut self, plugin: PluginStoped) {
+        match self.views.get_mut(&plugin.view_id) {
+            Some(view) => {
+                self.message = Some(if view.plugin_stopped(&plugin.plugin) {
+                    Message::Info(format!("plugin {} stopped", plugin.plugin))
+                } else {
+                    Message::Error(format!("plugin {} stopped unexpectedly", plugin.plugin))
+                });
+            }
+            None => self
+                .delayed_events
+                .push(CoreEvent::Notify(XiNotification::PluginStoped(plugin))),
+        }
+    }
+
+    /// Handle an "update_cmds" notification from Xi core.
+    fn update_cmds(&mut self, cmds: UpdateCmds) {
+        match self.views.get_mut(&cmds.view_id) {
+            Some(view) => view.set_plugin_commands(cmds.plugin, cmds.cmds),
+            None => self
+                .delayed_events
+                .push(CoreEvent::Notify(XiNotification::UpdateCmds(cmds))),
+        }
+    }
+
     /// Spawn a future that sends a "new_view" request to the core,
     /// and forwards the response back to the `Editor`.
     pub fn new_view(&mut self, file_path: Option<String>) {
         let response_tx = self.new_view_tx.clone();
+        let messages_tx = self.messages_tx.clone();
         let future = self
             .client
             .new_view(file_path.clone())
@@ -197,8 +560,12 @@ impl Editor {
                     .unwrap_or_else(|e| error!("failed to send \"new_view\" response: {:?}", e));
                 Ok(())
             })
-            .or_else(|client_error| {
+            .or_else(move |client_error| {
                 error!("failed to send \"new_view\" response: {:?}", client_error);
+                let message = Message::Error(format!("failed to open a view: {}", client_error));
+                messages_tx
+                    .unbounded_send(message)
+                    .unwrap_or_else(|e| error!("failed to send message: {:?}", e));
                 Ok(())
             });
         tokio::spawn(future);
@@ -207,17 +574,78 @@ impl Editor {
     /// Spawn a future that sends a "set_theme" notification to the
     /// core for the current view.
     pub fn set_theme(&mut self, theme: &str) {
-        tokio::spawn(self.client.set_theme(theme).map_err(|_| ()));
+        let messages_tx = self.messages_tx.clone();
+        tokio::spawn(self.client.set_theme(theme).map_err(move |e| {
+            let message = Message::Error(format!("failed to set the theme: {}", e));
+            messages_tx
+                .unbounded_send(message)
+                .unwrap_or_else(|e| error!("failed to send message: {:?}", e));
+        }));
     }
 
-    /// Spawn a future that sends a "save" notification to the core.
-    pub fn save(&mut self, view_id: Option<ViewId>) {
-        match self.views.get_mut(&view_id.unwrap_or(self.current_view)) {
-            Some(view) => view.save(),
-            None => warn!("cannot save view {:?}: not found", &view_id),
+    /// Change the language of the current buffer, or show it if none is
+    /// given.
+    pub fn set_language(&mut self, language: Option<&str>) {
+        let view = match self.views.get_mut(&self.current_view) {
+            Some(view) => view,
+            None => return,
+        };
+        let language = match language {
+            Some(language) => language,
+            None => {
+                self.message = Some(match view.language() {
+                    Some(language) => Message::Info(format!("language: {}", language)),
+                    None => Message::Error("the language of this buffer is not known".into()),
+                });
+                return;
+            }
+        };
+        // The languages are only checked once the core has listed them.
+        if self.languages.is_empty() || self.languages.iter().any(|l| l == language) {
+            view.set_language(language);
+        } else {
+            self.message = Some(Message::Error(format!("no language named {}", language)));
         }
     }
 
+    /// Spawn a future that sends a "save" notification to the core. If
+    /// a file name is given, the view is saved under this name from
+    /// now on. Return `false` if the view has no file name and none is
+    /// given, so that one can be asked for.
+    pub fn save(&mut self, view_id: Option<ViewId>, file: Option<String>) -> bool {
+        let synced_tx = (self.alerts, self.synced_tx.clone());
+        let view = match self.views.get_mut(&view_id.unwrap_or(self.current_view)) {
+            Some(view) => view,
+            None => {
+                warn!("cannot save view {:?}: not found", &view_id);
+                return true;
+            }
+        };
+        // The result is shown once the core has saved the buffer.
+        view.save(file, Some(synced_tx))
+    }
+
+    /// Save all the views that have a file name.
+    pub fn save_all(&mut self) {
+        let mut saving = 0;
+        let mut untitled = 0;
+        for view in self.views.values_mut() {
+            if view.save(None, None) {
+                saving += 1;
+            } else {
+                untitled += 1;
+            }
+        }
+        self.message = Some(if untitled == 0 {
+            Message::Info(format!("saving {} buffer(s)", saving))
+        } else {
+            Message::Error(format!(
+                "saving {} buffer(s), {} without a file name",
+                saving, untitled
+            ))
+        });
+    }
+
     pub fn back(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
             view.back();
@@ -230,6 +658,50 @@ impl Editor {
         }
     }
 
+    pub fn paste(&mut self, text: &str) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.paste(text);
+        }
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.undo(self.synced_tx.clone());
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.redo(self.synced_tx.clone());
+        }
+    }
+
+    pub fn copy(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.copy(self.clipboard_tx.clone());
+        }
+    }
+
+    pub fn cut(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.cut(self.clipboard_tx.clone());
+        }
+    }
+
+    /// Paste the content of the clipboard in the current view.
+    pub fn paste_clipboard(&mut self) {
+        if let Some(text) = self.clipboard.paste(self.pasted_tx.clone()) {
+            self.paste_text(&text);
+        }
+    }
+
+    /// Paste text read from the clipboard, unless there is none.
+    fn paste_text(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.paste(text);
+        }
+    }
+
     pub fn next_buffer(&mut self) {
         if let Some((dex, _, _)) = self.views.get_full(&self.current_view) {
             if dex + 1 == self.views.len() {
@@ -254,6 +726,168 @@ impl Editor {
         }
     }
 
+    /// Names of the open buffers: the file they edit, or the id of
+    /// the view for new buffers.
+    pub fn buffer_names(&self) -> Vec<String> {
+        self.views
+            .iter()
+            .map(|(id, view)| match view.file() {
+                Some(file) => file.to_string(),
+                None => id.to_string(),
+            })
+            .collect()
+    }
+
+    /// Names of the plugins of the current buffer.
+    pub fn plugin_names(&self) -> Vec<String> {
+        self.views
+            .get(&self.current_view)
+            .map_or_else(Vec::new, |view| {
+                view.plugins()
+                    .iter()
+                    .map(|plugin| plugin.name.clone())
+                    .collect()
+            })
+    }
+
+    /// Names of the commands provided by the plugins of the current
+    /// buffer.
+    pub fn plugin_commands(&self) -> Vec<String> {
+        self.views
+            .get(&self.current_view)
+            .map_or_else(Vec::new, View::plugin_commands)
+    }
+
+    /// Get the list of the plugins of the current buffer, with their
+    /// state.
+    pub fn list_plugins(&self) -> String {
+        let plugins: Vec<String> = self
+            .views
+            .get(&self.current_view)
+            .map_or(&[][..], View::plugins)
+            .iter()
+            .map(|plugin| {
+                let state = if plugin.running { "running" } else { "stopped" };
+                format!("{} ({})", plugin.name, state)
+            })
+            .collect();
+        if plugins.is_empty() {
+            "no plugins".to_string()
+        } else {
+            plugins.join(", ")
+        }
+    }
+
+    /// Start or stop a plugin for the current buffer.
+    pub fn toggle_plugin(&mut self, action: PluginAction, name: &str) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            if let Err(reason) = view.toggle_plugin(action, name) {
+                self.message = Some(Message::Error(reason));
+            }
+        }
+    }
+
+    /// Run a command provided by a plugin of the current buffer.
+    pub fn plugin_command(&mut self, name: &str) {
+        let found = match self.views.get_mut(&self.current_view) {
+            Some(view) => view.plugin_command(name),
+            None => false,
+        };
+        if !found {
+            self.message = Some(Message::Error(format!("unknown command: {}", name)));
+        }
+    }
+
+    /// Names of the buffers that have unsaved changes, except the one
+    /// of the given view.
+    pub fn dirty_buffers(&self, except: Option<ViewId>) -> Vec<String> {
+        self.buffer_names()
+            .into_iter()
+            .zip(self.views.iter())
+            .filter(|&(_, (id, view))| view.is_dirty() && Some(*id) != except)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Describe the open buffers, e.g. `1% foo.rs [+], 2 bar.rs`: `%`
+    /// marks the current buffer and `[+]` the ones with unsaved changes.
+    pub fn list_buffers(&self) -> String {
+        let buffers: Vec<String> = self
+            .buffer_names()
+            .into_iter()
+            .zip(self.views.iter())
+            .enumerate()
+            .map(|(i, (name, (id, view)))| {
+                let current = if *id == self.current_view { "%" } else { "" };
+                let dirty = if view.is_dirty() { " [+]" } else { "" };
+                format!("{}{} {}{}", i + 1, current, name, dirty)
+            })
+            .collect();
+        buffers.join(", ")
+    }
+
+    /// Close a buffer, given its number in the list of buffers (starting
+    /// at 1), or the current one. A buffer with unsaved changes is only
+    /// closed if `force` is set. The next buffer, or the previous one if
+    /// it was the last, becomes the current one.
+    pub fn close(&mut self, buffer: Option<usize>, force: bool) -> bool {
+        let view_id = match buffer {
+            Some(n) => match self.views.get_index(n.wrapping_sub(1)) {
+                Some((view_id, _)) => *view_id,
+                None => {
+                    self.message = Some(Message::Error(format!("No buffer {}", n)));
+                    return false;
+                }
+            },
+            None => self.current_view,
+        };
+        let dex = match self.views.get_full(&view_id) {
+            Some((dex, _, view)) if view.is_dirty() && !force => {
+                let name = &self.buffer_names()[dex];
+                let message = format!(
+                    "{} has unsaved changes: save it, or use c! or bd! to close it anyway",
+                    name
+                );
+                self.message = Some(Message::Error(message));
+                return false;
+            }
+            Some((dex, _, _)) => dex,
+            None => return false,
+        };
+
+        let messages_tx = self.messages_tx.clone();
+        let future = self.client.close_view(view_id).map_err(move |e| {
+            error!("failed to close view {}: {}", view_id, e);
+            let message = Message::Error(format!("failed to close the view: {}", e));
+            messages_tx
+                .unbounded_send(message)
+                .unwrap_or_else(|e| error!("failed to send message: {:?}", e));
+        });
+        tokio::spawn(future);
+
+        // Remove the view, keeping the order of the others.
+        self.views.retain(|id, _| *id != view_id);
+        if view_id == self.current_view {
+            let dex = dex.min(self.views.len().saturating_sub(1));
+            if let Some((id, _)) = self.views.get_index(dex) {
+                self.current_view = *id;
+            }
+        }
+        true
+    }
+
+    pub fn switch_buffer(&mut self, name: &str) {
+        let names = self.buffer_names();
+        match names.iter().position(|n| n == name) {
+            Some(dex) => {
+                if let Some((view, _)) = self.views.get_index(dex) {
+                    self.current_view = *view;
+                }
+            }
+            None => self.message = Some(Message::Error(format!("No buffer named {}", name))),
+        }
+    }
+
     pub fn move_left(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
             view.move_left();
@@ -290,11 +924,374 @@ impl Editor {
         }
     }
 
+    pub fn move_word_left(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.move_word_left();
+        }
+    }
+
+    pub fn move_word_right(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.move_word_right();
+        }
+    }
+
+    pub fn paragraph_up(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.paragraph_up();
+        }
+    }
+
+    pub fn paragraph_down(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.paragraph_down();
+        }
+    }
+
+    pub fn document_start(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.document_start();
+        }
+    }
+
+    pub fn document_end(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.document_end();
+        }
+    }
+
+    pub fn goto(&mut self, line: u64, column: Option<u64>) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.goto(line, column);
+        }
+    }
+
+    pub fn select_left(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_left();
+        }
+    }
+
+    pub fn select_right(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_right();
+        }
+    }
+
+    pub fn select_up(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_up();
+        }
+    }
+
+    pub fn select_down(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_down();
+        }
+    }
+
+    pub fn select_word_left(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_word_left();
+        }
+    }
+
+    pub fn select_word_right(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_word_right();
+        }
+    }
+
+    pub fn select_home(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_home();
+        }
+    }
+
+    pub fn select_end(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_end();
+        }
+    }
+
+    pub fn select_page_up(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_page_up();
+        }
+    }
+
+    pub fn select_page_down(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_page_down();
+        }
+    }
+
+    pub fn select_document_start(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_document_start();
+        }
+    }
+
+    pub fn select_document_end(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_document_end();
+        }
+    }
+
+    pub fn add_cursor_above(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.add_cursor_above();
+        }
+    }
+
+    pub fn add_cursor_below(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.add_cursor_below();
+        }
+    }
+
+    pub fn select_next_occurrence(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_next_occurrence();
+        }
+    }
+
+    pub fn select_all_occurrences(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.select_all_occurrences();
+        }
+    }
+
+    pub fn collapse_selections(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.collapse_selections();
+        }
+    }
+
+    /// Change an option for the current buffer, or for all of them. If
+    /// no value is given, show the value of the option in the current
+    /// buffer instead.
+    pub fn set_option(&mut self, setting: Setting, global: bool) {
+        let spec = setting.spec;
+        let value = match setting.value {
+            Some(value) => value,
+            None => {
+                let value = self
+                    .views
+                    .get(&self.current_view)
+                    .and_then(|view| view.option(spec));
+                self.message = Some(match value {
+                    Some(value) => Message::Info(format!("{}={}", spec.name, value)),
+                    None => Message::Error(format!("the value of {} is not known", spec.name)),
+                });
+                return;
+            }
+        };
+        if !global {
+            if let Some(view) = self.views.get_mut(&self.current_view) {
+                view.set_option(spec, value);
+            }
+            return;
+        }
+        if spec.core {
+            let messages_tx = self.messages_tx.clone();
+            let changes = json!({ spec.name: value.to_json() });
+            tokio::spawn(
+                self.client
+                    .modify_user_config("general", changes)
+                    .map_err(move |e| {
+                        let message = Message::Error(format!("failed to set the option: {}", e));
+                        messages_tx
+                            .unbounded_send(message)
+                            .unwrap_or_else(|e| error!("failed to send message: {:?}", e));
+                    }),
+            );
+        } else {
+            self.view_options.retain(|&(s, _)| s.name != spec.name);
+            self.view_options.push((spec, value));
+        }
+        for view in self.views.values_mut() {
+            view.option_changed(spec, value);
+        }
+    }
+
     pub fn toggle_line_numbers(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
             view.toggle_line_numbers();
         }
     }
+
+    /// Select the lines of a range in the current view. Return `false`
+    /// if they can't be selected.
+    pub fn select_lines(&mut self, range: Range) -> bool {
+        let view = match self.views.get_mut(&self.current_view) {
+            Some(view) => view,
+            None => return false,
+        };
+        match view.select_lines(range) {
+            Ok(()) => true,
+            Err(reason) => {
+                self.message = Some(Message::Error(reason));
+                false
+            }
+        }
+    }
+
+    pub fn search(&mut self, query: &SearchQuery) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.find(query);
+        }
+    }
+
+    pub fn search_next(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.find_next();
+        }
+    }
+
+    pub fn search_prev(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.find_prev();
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.start_search();
+        }
+    }
+
+    pub fn update_search(&mut self, text: &str) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.update_search(text);
+        }
+    }
+
+    pub fn end_search(&mut self, keep: bool) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.end_search(keep);
+        }
+    }
+
+    /// Replace the matches of a search in the current view. Unless all
+    /// of them are replaced at once, the user is asked for each one.
+    pub fn replace(&mut self, query: &SearchQuery, replacement: &str, all: bool) {
+        self.finish_replace();
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.replace(query, replacement);
+            if all {
+                view.replace_all();
+            }
+            self.replace = Some(Replace {
+                view_id: self.current_view,
+                all,
+                replacement: None,
+                remaining: None,
+                replaced: 0,
+            });
+        }
+    }
+
+    /// Handle a key while the user is asked whether to replace a match:
+    /// `y` replaces it, `n` skips it, `a` replaces all the matches and
+    /// `q` or Escape stops.
+    fn handle_replace_input(&mut self, event: &TermionEvent) {
+        let replace = match self.replace {
+            Some(ref mut replace) => replace,
+            None => return,
+        };
+        let view = match self.views.get_mut(&replace.view_id) {
+            Some(view) if replace.view_id == self.current_view => view,
+            _ => {
+                self.replace = None;
+                return;
+            }
+        };
+        let remaining = replace.remaining.unwrap_or(0);
+        match *event {
+            TermionEvent::Key(Key::Char('y')) => {
+                view.replace_next();
+                replace.replaced += 1;
+                replace.remaining = Some(remaining.saturating_sub(1));
+            }
+            TermionEvent::Key(Key::Char('n')) => {
+                view.find_next();
+                replace.remaining = Some(remaining.saturating_sub(1));
+            }
+            TermionEvent::Key(Key::Char('a')) => {
+                view.replace_all();
+                replace.replaced += remaining;
+                replace.remaining = Some(0);
+            }
+            TermionEvent::Key(Key::Char('q')) | TermionEvent::Key(Key::Esc) => {
+                replace.remaining = Some(0);
+            }
+            _ => {}
+        }
+        self.review_replace();
+    }
+
+    /// Ask the user whether to replace the current match, or finish the
+    /// replacement if there is no match left.
+    fn review_replace(&mut self) {
+        let (view_id, replacement) = match self.replace {
+            Some(ref replace) if replace.remaining == Some(0) => {
+                self.finish_replace();
+                return;
+            }
+            Some(ref replace) if !replace.all => (replace.view_id, replace.replacement.clone()),
+            _ => return,
+        };
+        let mut question = match replacement {
+            Some(replacement) => format!("replace with {}? (y/n/a/q)", replacement),
+            None => "replace? (y/n/a/q)".to_string(),
+        };
+        let status = self.views.get(&view_id).and_then(View::match_status);
+        if let Some(Message::Info(status)) = status {
+            question = format!("{}: {}", status, question);
+        }
+        self.message = Some(Message::Info(question));
+    }
+
+    /// End the replacement in progress, if any, and tell how many
+    /// matches were replaced.
+    fn finish_replace(&mut self) {
+        let replace = match self.replace.take() {
+            Some(replace) => replace,
+            None => return,
+        };
+        if let Some(view) = self.views.get_mut(&replace.view_id) {
+            view.hide_matches();
+        }
+        self.message = Some(Message::Info(match replace.replaced {
+            1 => "replaced 1 match".to_string(),
+            n => format!("replaced {} matches", n),
+        }));
+    }
+
+    pub fn hide_matches(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.hide_matches();
+        }
+    }
+
+    pub fn sort_lines(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.sort_lines();
+        }
+    }
+
+    pub fn indent(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.indent();
+        }
+    }
+
+    pub fn outdent(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.outdent();
+        }
+    }
 }
 
 /// Methods ment to be called by the tui struct
@@ -305,12 +1302,16 @@ impl Editor {
     //  - the style changed
     //  - the terminal size changed
     pub fn render<W: Write>(&mut self, term: &mut W) -> Result<(), Error> {
+        self.clipboard.flush(term)?;
         if let Some(view) = self.views.get_mut(&self.current_view) {
             debug!("rendering the current view");
             view.render(term, &self.styles)?;
         } else {
             warn!("no view to render");
         }
+        if let Some(ref message) = self.message {
+            message.render(term, self.size.1, self.size.0)?;
+        }
         Ok(())
     }
 }
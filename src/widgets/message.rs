@@ -0,0 +1,38 @@
+//! Messages shown to the user on the last line of the screen, until
+//! the next key press.
+use std::io::{self, Write};
+
+use termion::clear::CurrentLine as ClearLine;
+use termion::color;
+use termion::cursor::{Goto, Restore, Save};
+use termion::style;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// The outcome of a command.
+    Info(String),
+    /// A command, or a request to the core, failed.
+    Error(String),
+}
+
+impl Message {
+    pub fn render<W: Write>(&self, w: &mut W, row: u16, width: u16) -> io::Result<()> {
+        let text = match *self {
+            Message::Info(ref text) | Message::Error(ref text) => text,
+        };
+        let text: String = text.chars().take(usize::from(width)).collect();
+        write!(w, "{}{}{}", Save, Goto(1, row), ClearLine)?;
+        match *self {
+            Message::Info(_) => write!(w, "{}", text)?,
+            Message::Error(_) => write!(
+                w,
+                "{}{}{}{}",
+                style::Bold,
+                color::Fg(color::Red),
+                text,
+                style::Reset
+            )?,
+        }
+        write!(w, "{}", Restore)
+    }
+}
@@ -8,3 +8,6 @@ pub use self::editor::Editor;
 
 mod command_prompt;
 pub use self::command_prompt::CommandPrompt;
+
+mod message;
+pub use self::message::Message;
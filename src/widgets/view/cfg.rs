@@ -1,17 +1,86 @@
+use core::OptionValue;
+
+/// How lines are numbered in the gutter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GutterMode {
+    Absolute,
+    /// Lines are numbered relatively to the line of the cursor, which
+    /// shows its own number.
+    Relative,
+    Off,
+}
+
+/// Which spaces and tabs are made visible.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Whitespace {
+    Hidden,
+    /// Only those at the end of the lines.
+    Trailing,
+    All,
+}
+
 pub struct ViewConfig {
     // Gutter Settings
-    pub display_gutter: bool,
+    pub gutter: GutterMode,
     pub gutter_size: u16,
     // Tab Settings
     pub tab_size: u16,
+    // Display Settings
+    pub wrap: bool,
+    pub whitespace: Whitespace,
 }
 
 impl Default for ViewConfig {
     fn default() -> ViewConfig {
         ViewConfig {
-            display_gutter: true,
+            gutter: GutterMode::Absolute,
             gutter_size: 0,
             tab_size: 4,
+            wrap: false,
+            whitespace: Whitespace::Hidden,
+        }
+    }
+}
+
+impl ViewConfig {
+    /// Change one of the options handled by xi-term. The value has
+    /// already been checked against the type of the option.
+    pub fn set(&mut self, name: &str, value: OptionValue) {
+        match (name, value) {
+            ("gutter", OptionValue::Choice(mode)) => {
+                self.gutter = match mode {
+                    "relative" => GutterMode::Relative,
+                    "none" => GutterMode::Off,
+                    _ => GutterMode::Absolute,
+                }
+            }
+            ("wrap", OptionValue::Bool(wrap)) => self.wrap = wrap,
+            ("whitespace", OptionValue::Choice(shown)) => {
+                self.whitespace = match shown {
+                    "trailing" => Whitespace::Trailing,
+                    "all" => Whitespace::All,
+                    _ => Whitespace::Hidden,
+                }
+            }
+            _ => warn!("cannot set option {} to {}", name, value),
+        }
+    }
+
+    /// Get the value of one of the options handled by xi-term.
+    pub fn get(&self, name: &str) -> Option<OptionValue> {
+        match name {
+            "gutter" => Some(OptionValue::Choice(match self.gutter {
+                GutterMode::Absolute => "absolute",
+                GutterMode::Relative => "relative",
+                GutterMode::Off => "none",
+            })),
+            "wrap" => Some(OptionValue::Bool(self.wrap)),
+            "whitespace" => Some(OptionValue::Choice(match self.whitespace {
+                Whitespace::Hidden => "none",
+                Whitespace::Trailing => "trailing",
+                Whitespace::All => "all",
+            })),
+            _ => None,
         }
     }
 }
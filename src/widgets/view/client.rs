@@ -1,105 +1,714 @@
+use futures::future::{self, Either};
+use futures::sync::mpsc::UnboundedSender;
+use futures::sync::oneshot;
 use futures::Future;
+use serde_json::Value;
 use tokio::spawn;
 use xrl;
+use xrl::ModifySelection;
+
+use core::SearchQuery;
+use widgets::Message;
+
+/// What to check once the core has handled a notification, i.e. has
+/// sent the updates and the alerts it caused.
+pub enum Synced {
+    /// Show the message if the view has still received the given
+    /// number of updates, e.g. "Nothing to undo".
+    Unchanged(xrl::ViewId, u64, Message),
+    /// The view was saved, unless the core sent an alert: the given
+    /// number of alerts had been received before the save.
+    Saved(xrl::ViewId, u64),
+}
 
 pub struct Client {
     inner: xrl::Client,
     view_id: xrl::ViewId,
+    /// Channel into which the failures of the requests are reported.
+    messages: UnboundedSender<Message>,
+    /// Resolved once the last lines selected with `select_lines` have
+    /// been sent to the core. Requests are sent ahead of the pending
+    /// notifications, so the requests that act on the selection must
+    /// wait for it.
+    selected: Option<oneshot::Receiver<()>>,
 }
 
 impl Client {
-    pub fn new(client: xrl::Client, view_id: xrl::ViewId) -> Self {
+    pub fn new(
+        client: xrl::Client,
+        view_id: xrl::ViewId,
+        messages: UnboundedSender<Message>,
+    ) -> Self {
         Client {
             inner: client,
             view_id,
+            messages,
+            selected: None,
+        }
+    }
+
+    /// Get a function that reports the failure of a request to the
+    /// user.
+    fn report(&self, request: &'static str) -> impl FnOnce(xrl::ClientError) {
+        let messages = self.messages.clone();
+        move |e| {
+            error!("\"{}\" failed: {}", request, e);
+            messages
+                .unbounded_send(Message::Error(format!("{} failed: {}", request, e)))
+                .unwrap_or_else(|e| error!("failed to send message: {:?}", e));
         }
     }
 
     pub fn insert(&mut self, character: char) {
-        let f = self.inner.char(self.view_id, character).map_err(|_| ());
+        let f = self
+            .inner
+            .char(self.view_id, character)
+            .map_err(self.report("insert"));
+        spawn(f);
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        let f = self
+            .inner
+            .insert(self.view_id, text)
+            .map_err(self.report("insert_str"));
+        spawn(f);
+    }
+
+    pub fn paste(&mut self, text: &str) {
+        let f = self
+            .inner
+            .paste(self.view_id, text)
+            .map_err(self.report("paste"));
         spawn(f);
     }
 
     pub fn insert_newline(&mut self) {
-        let f = self.inner.insert_newline(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .insert_newline(self.view_id)
+            .map_err(self.report("insert_newline"));
         spawn(f);
     }
 
     pub fn insert_tab(&mut self) {
-        let f = self.inner.insert_tab(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .insert_tab(self.view_id)
+            .map_err(self.report("insert_tab"));
+        spawn(f);
+    }
+
+    /// Change core options for this view only.
+    pub fn modify_config(&mut self, changes: Value) {
+        let params = json!({
+            "domain": { "user_override": self.view_id },
+            "changes": changes,
+        });
+        let f = self
+            .inner
+            .notify("modify_user_config", params)
+            .map_err(self.report("modify_user_config"));
+        spawn(f);
+    }
+
+    pub fn set_language(&mut self, language: &str) {
+        let f = self
+            .inner
+            .set_language(self.view_id, language)
+            .map_err(self.report("set_language"));
+        spawn(f);
+    }
+
+    pub fn start_plugin(&mut self, name: &str) {
+        let f = self
+            .inner
+            .start_plugin(self.view_id, name)
+            .map_err(self.report("start_plugin"));
+        spawn(f);
+    }
+
+    pub fn stop_plugin(&mut self, name: &str) {
+        let f = self
+            .inner
+            .stop_plugin(self.view_id, name)
+            .map_err(self.report("stop_plugin"));
+        spawn(f);
+    }
+
+    /// Run a command provided by a plugin.
+    pub fn plugin_command(&mut self, plugin: &str, command: &str) {
+        let f = self
+            .inner
+            .notify_plugin(self.view_id, plugin, command, &json!({}))
+            .map_err(self.report("plugin_rpc"));
         spawn(f);
     }
 
     pub fn scroll(&mut self, start: u64, end: u64) {
-        let f = self.inner.scroll(self.view_id, start, end).map_err(|_| ());
+        let f = self
+            .inner
+            .scroll(self.view_id, start, end)
+            .map_err(self.report("scroll"));
         spawn(f);
     }
 
     pub fn down(&mut self) {
-        let f = self.inner.down(self.view_id).map_err(|_| ());
+        let f = self.inner.down(self.view_id).map_err(self.report("down"));
         spawn(f);
     }
 
     pub fn up(&mut self) {
-        let f = self.inner.up(self.view_id).map_err(|_| ());
+        let f = self.inner.up(self.view_id).map_err(self.report("up"));
         spawn(f);
     }
 
     pub fn right(&mut self) {
-        let f = self.inner.right(self.view_id).map_err(|_| ());
+        let f = self.inner.right(self.view_id).map_err(self.report("right"));
         spawn(f);
     }
 
     pub fn left(&mut self) {
-        let f = self.inner.left(self.view_id).map_err(|_| ());
+        let f = self.inner.left(self.view_id).map_err(self.report("left"));
         spawn(f);
     }
 
     pub fn page_down(&mut self) {
-        let f = self.inner.page_down(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .page_down(self.view_id)
+            .map_err(self.report("page_down"));
         spawn(f);
     }
 
     pub fn page_up(&mut self) {
-        let f = self.inner.page_up(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .page_up(self.view_id)
+            .map_err(self.report("page_up"));
         spawn(f);
     }
 
     pub fn home(&mut self) {
-        let f = self.inner.line_start(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .line_start(self.view_id)
+            .map_err(self.report("home"));
         spawn(f);
     }
 
     pub fn end(&mut self) {
-        let f = self.inner.line_end(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .line_end(self.view_id)
+            .map_err(self.report("end"));
+        spawn(f);
+    }
+
+    pub fn move_word_left(&mut self) {
+        let f = self
+            .inner
+            .move_word_left(self.view_id)
+            .map_err(self.report("move_word_left"));
+        spawn(f);
+    }
+
+    pub fn move_word_right(&mut self) {
+        let f = self
+            .inner
+            .move_word_right(self.view_id)
+            .map_err(self.report("move_word_right"));
+        spawn(f);
+    }
+
+    pub fn document_start(&mut self) {
+        let f = self
+            .inner
+            .document_begin(self.view_id)
+            .map_err(self.report("document_start"));
+        spawn(f);
+    }
+
+    pub fn document_end(&mut self) {
+        let f = self
+            .inner
+            .document_end(self.view_id)
+            .map_err(self.report("document_end"));
+        spawn(f);
+    }
+
+    pub fn paragraph_up(&mut self) {
+        let f = self
+            .inner
+            .edit_notify(
+                self.view_id,
+                "move_to_beginning_of_paragraph",
+                None as Option<Value>,
+            )
+            .map_err(self.report("paragraph_up"));
+        spawn(f);
+    }
+
+    pub fn paragraph_down(&mut self) {
+        let f = self
+            .inner
+            .edit_notify(
+                self.view_id,
+                "move_to_end_of_paragraph",
+                None as Option<Value>,
+            )
+            .map_err(self.report("paragraph_down"));
+        spawn(f);
+    }
+
+    pub fn goto_line(&mut self, line: u64) {
+        let f = self
+            .inner
+            .goto_line(self.view_id, line)
+            .map_err(self.report("goto_line"));
+        spawn(f);
+    }
+
+    pub fn select_left(&mut self) {
+        let f = self
+            .inner
+            .left_sel(self.view_id)
+            .map_err(self.report("select_left"));
+        spawn(f);
+    }
+
+    pub fn select_right(&mut self) {
+        let f = self
+            .inner
+            .right_sel(self.view_id)
+            .map_err(self.report("select_right"));
+        spawn(f);
+    }
+
+    pub fn select_up(&mut self) {
+        let f = self
+            .inner
+            .up_sel(self.view_id)
+            .map_err(self.report("select_up"));
+        spawn(f);
+    }
+
+    pub fn select_down(&mut self) {
+        let f = self
+            .inner
+            .down_sel(self.view_id)
+            .map_err(self.report("select_down"));
+        spawn(f);
+    }
+
+    pub fn select_word_left(&mut self) {
+        let f = self
+            .inner
+            .move_word_left_sel(self.view_id)
+            .map_err(self.report("select_word_left"));
+        spawn(f);
+    }
+
+    pub fn select_word_right(&mut self) {
+        let f = self
+            .inner
+            .move_word_right_sel(self.view_id)
+            .map_err(self.report("select_word_right"));
+        spawn(f);
+    }
+
+    pub fn select_home(&mut self) {
+        let f = self
+            .inner
+            .line_start_sel(self.view_id)
+            .map_err(self.report("select_home"));
+        spawn(f);
+    }
+
+    pub fn select_end(&mut self) {
+        let f = self
+            .inner
+            .line_end_sel(self.view_id)
+            .map_err(self.report("select_end"));
+        spawn(f);
+    }
+
+    pub fn select_page_up(&mut self) {
+        let f = self
+            .inner
+            .page_up_sel(self.view_id)
+            .map_err(self.report("select_page_up"));
+        spawn(f);
+    }
+
+    pub fn select_page_down(&mut self) {
+        let f = self
+            .inner
+            .page_down_sel(self.view_id)
+            .map_err(self.report("select_page_down"));
+        spawn(f);
+    }
+
+    pub fn select_document_start(&mut self) {
+        let f = self
+            .inner
+            .document_begin_sel(self.view_id)
+            .map_err(self.report("select_document_start"));
+        spawn(f);
+    }
+
+    pub fn select_document_end(&mut self) {
+        let f = self
+            .inner
+            .document_end_sel(self.view_id)
+            .map_err(self.report("select_document_end"));
+        spawn(f);
+    }
+
+    /// Undo the last edit. Once the core has sent the update for the
+    /// undo, if there was one, `message` is sent to `synced_tx`, to be
+    /// shown if the view has still received `updates` updates.
+    pub fn undo(&mut self, updates: u64, message: Message, synced_tx: UnboundedSender<Synced>) {
+        let f = self.inner.undo(self.view_id);
+        let synced = Synced::Unchanged(self.view_id, updates, message);
+        self.then_synced("undo", f, synced, synced_tx);
+    }
+
+    /// Redo the last undone edit. Like for `undo`, `message` is sent to
+    /// `synced_tx` afterwards.
+    pub fn redo(&mut self, updates: u64, message: Message, synced_tx: UnboundedSender<Synced>) {
+        let f = self.inner.redo(self.view_id);
+        let synced = Synced::Unchanged(self.view_id, updates, message);
+        self.then_synced("redo", f, synced, synced_tx);
+    }
+
+    /// Send a notification, and then `synced` to `synced_tx` after the
+    /// core has sent the updates and alerts the notification caused,
+    /// if any. The core doesn't answer notifications, so a "copy"
+    /// request, that changes nothing, is sent after it: the core
+    /// handles them in order, so it answers the request after sending
+    /// them.
+    fn then_synced<F>(
+        &mut self,
+        request: &'static str,
+        notification: F,
+        synced: Synced,
+        synced_tx: UnboundedSender<Synced>,
+    ) where
+        F: Future<Item = (), Error = xrl::ClientError> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        let view_id = self.view_id;
+        let f = notification
+            .and_then(move |_| inner.copy(view_id))
+            .map(move |_| {
+                synced_tx
+                    .unbounded_send(synced)
+                    .unwrap_or_else(|e| error!("failed to send {} result: {:?}", request, e));
+            })
+            .map_err(self.report(request));
+        spawn(f);
+    }
+
+    pub fn add_cursor_above(&mut self) {
+        let f = self
+            .inner
+            .edit_notify(self.view_id, "add_selection_above", None as Option<Value>)
+            .map_err(self.report("add_cursor_above"));
+        spawn(f);
+    }
+
+    pub fn add_cursor_below(&mut self) {
+        let f = self
+            .inner
+            .edit_notify(self.view_id, "add_selection_below", None as Option<Value>)
+            .map_err(self.report("add_cursor_below"));
+        spawn(f);
+    }
+
+    /// Use the current selection, or the word under the cursor if the
+    /// selection is empty, as search query.
+    fn selection_for_find(&mut self) -> impl Future<Item = (), Error = xrl::ClientError> {
+        self.inner.edit_notify(
+            self.view_id,
+            "selection_for_find",
+            Some(json!({ "case_sensitive": true })),
+        )
+    }
+
+    pub fn select_next_occurrence(&mut self) {
+        let inner = self.inner.clone();
+        let view_id = self.view_id;
+        let f = self
+            .selection_for_find()
+            .and_then(move |_| inner.find_next(view_id, true, false, ModifySelection::Add))
+            .map_err(self.report("select_next_occurrence"));
+        spawn(f);
+    }
+
+    pub fn select_all_occurrences(&mut self) {
+        let inner = self.inner.clone();
+        let view_id = self.view_id;
+        let f = self
+            .selection_for_find()
+            .and_then(move |_| inner.find_all(view_id))
+            .map_err(self.report("select_all_occurrences"));
+        spawn(f);
+    }
+
+    /// Search for a query, highlight its matches, and select the
+    /// nearest one.
+    pub fn find(&mut self, query: &SearchQuery) {
+        let f = self
+            .inner
+            .find(
+                self.view_id,
+                &query.text,
+                query.case_sensitive,
+                query.regex,
+                query.whole_words,
+            )
+            .map_err(self.report("find"));
+        spawn(f);
+        self.highlight_find(true);
+        let f = self
+            .inner
+            .find_next(self.view_id, true, true, ModifySelection::Set)
+            .map_err(self.report("find"));
+        spawn(f);
+    }
+
+    pub fn find_next(&mut self) {
+        let f = self
+            .inner
+            .find_next(self.view_id, true, false, ModifySelection::Set)
+            .map_err(self.report("find_next"));
+        spawn(f);
+    }
+
+    pub fn find_prev(&mut self) {
+        let f = self
+            .inner
+            .find_prev(self.view_id, true, false, ModifySelection::Set)
+            .map_err(self.report("find_prev"));
+        spawn(f);
+    }
+
+    pub fn replace(&mut self, replacement: &str) {
+        let f = self
+            .inner
+            .replace(self.view_id, replacement, false)
+            .map_err(self.report("replace"));
+        spawn(f);
+    }
+
+    pub fn replace_next(&mut self) {
+        let f = self
+            .inner
+            .replace_next(self.view_id)
+            .map_err(self.report("replace_next"));
+        spawn(f);
+    }
+
+    pub fn replace_all(&mut self) {
+        let f = self
+            .inner
+            .replace_all(self.view_id)
+            .map_err(self.report("replace_all"));
+        spawn(f);
+    }
+
+    pub fn highlight_find(&mut self, visible: bool) {
+        let f = self
+            .inner
+            .highlight_find(self.view_id, visible)
+            .map_err(self.report("highlight_find"));
+        spawn(f);
+    }
+
+    pub fn collapse_selections(&mut self) {
+        let f = self
+            .inner
+            .collapse_selections(self.view_id)
+            .map_err(self.report("collapse_selections"));
         spawn(f);
     }
 
     pub fn delete(&mut self) {
-        let f = self.inner.delete(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .delete(self.view_id)
+            .map_err(self.report("delete"));
         spawn(f);
     }
 
     pub fn backspace(&mut self) {
-        let f = self.inner.backspace(self.view_id).map_err(|_| ());
+        let f = self
+            .inner
+            .backspace(self.view_id)
+            .map_err(self.report("backspace"));
         spawn(f);
     }
 
-    pub fn save(&mut self, file: &str) {
-        let f = self.inner.save(self.view_id, file).map_err(|_| ());
+    /// Copy the selection. The copied text is sent to `clipboard_tx`.
+    pub fn copy(&mut self, clipboard_tx: UnboundedSender<String>) {
+        let f = self
+            .inner
+            .copy(self.view_id)
+            .map(move |value| {
+                if let Some(text) = value.as_str() {
+                    clipboard_tx
+                        .unbounded_send(text.to_string())
+                        .unwrap_or_else(|e| error!("failed to send copied text: {:?}", e));
+                }
+            })
+            .map_err(self.report("copy"));
+        spawn(f);
+    }
+
+    /// Cut the selection. The text that was cut is sent to
+    /// `clipboard_tx`.
+    pub fn cut(&mut self, clipboard_tx: UnboundedSender<String>) {
+        let f = self
+            .inner
+            .cut(self.view_id)
+            .map(move |value| {
+                if let Some(text) = value.as_str() {
+                    clipboard_tx
+                        .unbounded_send(text.to_string())
+                        .unwrap_or_else(|e| error!("failed to send cut text: {:?}", e));
+                }
+            })
+            .map_err(self.report("cut"));
         spawn(f);
     }
 
+    /// Save the buffer to the given file. The core reports failures
+    /// with an alert. If `synced_tx` is given, with the number of
+    /// alerts received so far, `Synced::Saved` is sent to it once the
+    /// core has handled the save.
+    pub fn save(&mut self, file: &str, synced_tx: Option<(u64, UnboundedSender<Synced>)>) {
+        let f = self.inner.save(self.view_id, file);
+        match synced_tx {
+            Some((alerts, synced_tx)) => {
+                let synced = Synced::Saved(self.view_id, alerts);
+                self.then_synced("save", f, synced, synced_tx);
+            }
+            None => {
+                spawn(f.map_err(self.report("save")));
+            }
+        }
+    }
+
     pub fn click(&mut self, line: u64, column: u64) {
         let f = self
             .inner
             .click_point_select(self.view_id, line, column)
-            .map_err(|_| ());
+            .map_err(self.report("click"));
+        spawn(f);
+    }
+
+    pub fn word_select(&mut self, line: u64, column: u64) {
+        let f = self
+            .inner
+            .click_word_select(self.view_id, line, column)
+            .map_err(self.report("word_select"));
+        spawn(f);
+    }
+
+    pub fn line_select(&mut self, line: u64, column: u64) {
+        self.gesture(line, column, "line_select")
+    }
+
+    /// Extend the current selection up to the given position.
+    pub fn range_select(&mut self, line: u64, column: u64) {
+        let f = self
+            .inner
+            .click_range_select(self.view_id, line, column)
+            .map_err(self.report("range_select"));
+        spawn(f);
+    }
+
+    /// Send a gesture that xrl has no method for: its
+    /// `click_line_select` sends a `range_select` gesture instead of a
+    /// `line_select` one.
+    fn gesture(&mut self, line: u64, column: u64, ty: &str) {
+        let params = json!({"line": line, "col": column, "ty": ty});
+        let f = self
+            .inner
+            .edit_notify(self.view_id, "gesture", Some(params))
+            .map_err(self.report("gesture"));
+        spawn(f);
+    }
+
+    /// Select the lines from `first` to `last` (numbered from 0),
+    /// including the newline that ends the last one.
+    pub fn select_lines(&mut self, first: u64, last: u64) {
+        self.click(first, 0);
+        let (tx, rx) = oneshot::channel();
+        let f = self
+            .inner
+            .click_range_select(self.view_id, last + 1, 0)
+            .then(move |result| {
+                let _ = tx.send(());
+                result
+            })
+            .map_err(self.report("select_lines"));
+        spawn(f);
+        self.selected = Some(rx);
+    }
+
+    /// Sort the lines of the selection.
+    pub fn sort_lines(&mut self) {
+        let selected = match self.selected.take() {
+            Some(rx) => Either::A(rx.then(|_| Ok(()))),
+            None => Either::B(future::ok(())),
+        };
+        let copy = self.inner.clone();
+        let insert = self.inner.clone();
+        let view_id = self.view_id;
+        let f = selected
+            .and_then(move |_| copy.copy(view_id))
+            .and_then(move |value| match value.as_str() {
+                Some(text) => Either::A(insert.insert(view_id, &sort_text(text))),
+                None => Either::B(future::ok(())),
+            })
+            .map_err(self.report("sort_lines"));
+        spawn(f);
+    }
+
+    pub fn indent(&mut self) {
+        let f = self
+            .inner
+            .edit_notify(self.view_id, "indent", None as Option<Value>)
+            .map_err(self.report("indent"));
+        spawn(f);
+    }
+
+    pub fn outdent(&mut self) {
+        let f = self
+            .inner
+            .outdent(self.view_id)
+            .map_err(self.report("outdent"));
         spawn(f);
     }
 
     pub fn drag(&mut self, line: u64, column: u64) {
-        let f = self.inner.drag(self.view_id, line, column).map_err(|_| ());
+        let f = self
+            .inner
+            .drag(self.view_id, line, column)
+            .map_err(self.report("drag"));
         spawn(f);
     }
 }
+
+/// Sort the lines of a text, keeping its final newline if it has one.
+fn sort_text(text: &str) -> String {
+    let (text, newline) = match text.strip_suffix('\n') {
+        Some(text) => (text, "\n"),
+        None => (text, ""),
+    };
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    lines.sort();
+    format!("{}{}", lines.join("\n"), newline)
+}
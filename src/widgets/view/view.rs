@@ -1,15 +1,24 @@
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::collections::HashMap;
 use std::io::Write;
+use std::time::{Duration, Instant};
 
 use failure::Error;
+use futures::sync::mpsc::UnboundedSender;
+use termion;
 use termion::clear::CurrentLine as ClearLine;
 use termion::cursor::Goto;
 use termion::event::{Event, Key, MouseButton, MouseEvent};
-use xrl::{ConfigChanges, Line, LineCache, Style, Update};
+use xrl::{ConfigChanges, FindStatus, Line, LineCache, Style, Update};
 
-use super::cfg::ViewConfig;
-use super::client::Client;
+use core::{
+    parse_unsupported, InputEvent, Modifiers, OptionSpec, OptionValue, PluginAction, Range,
+    SearchQuery,
+};
+use widgets::Message;
+
+use super::cfg::{GutterMode, ViewConfig, Whitespace};
+use super::client::{Client, Synced};
 use super::style::{reset_style, set_style};
 use super::window::Window;
 
@@ -19,13 +28,70 @@ pub struct Cursor {
     pub column: u64,
 }
 
+/// Maximum delay between two clicks for them to count as a
+/// double-click (or triple-click).
+const MULTI_CLICK_DELAY: Duration = Duration::from_millis(500);
+
+/// The matches of the current search, as reported by the core.
+#[derive(Debug)]
+struct Search {
+    text: String,
+    matches: u64,
+    /// The line of each match, starting at 1.
+    lines: Vec<u64>,
+}
+
+/// A plugin of a buffer.
+#[derive(Debug)]
+pub struct Plugin {
+    pub name: String,
+    pub running: bool,
+}
+
+/// A left click, remembered to detect double and triple clicks.
+#[derive(Debug)]
+struct Click {
+    time: Instant,
+    line: u64,
+    column: u64,
+    count: u8,
+}
+
 pub struct View {
     cache: LineCache,
     cursor: Cursor,
     window: Window,
+    /// Width of the screen.
+    width: u16,
     file: Option<String>,
     client: Client,
     cfg: ViewConfig,
+    /// The core options of the buffer, as last reported by the core or
+    /// set by the user.
+    core_options: HashMap<&'static str, OptionValue>,
+    last_click: Option<Click>,
+    /// Whether the buffer has no unsaved changes, as reported by the
+    /// core.
+    pristine: bool,
+    /// Number of updates received from the core, to tell whether a
+    /// notification changed the buffer.
+    updates: u64,
+    search: Option<Search>,
+    /// Whether the user just moved to a match, and should be told
+    /// which one it is.
+    report_match: bool,
+    /// Line and column of the cursor when the incremental search
+    /// started, if one is in progress.
+    search_origin: Option<(u64, u64)>,
+    /// The plugins of the buffer, and whether they are running.
+    plugins: Vec<Plugin>,
+    /// The commands provided by each plugin.
+    plugin_commands: HashMap<String, Vec<String>>,
+    /// The plugins the user asked to start or stop, until the core
+    /// reports that they did.
+    plugin_requests: Vec<String>,
+    /// The language of the buffer, as reported by the core.
+    language: Option<String>,
 }
 
 impl View {
@@ -34,25 +100,198 @@ impl View {
             cache: LineCache::default(),
             cursor: Default::default(),
             window: Window::new(),
+            width: 0,
             cfg: ViewConfig::default(),
+            core_options: HashMap::new(),
+            last_click: None,
+            pristine: true,
+            updates: 0,
+            search: None,
+            report_match: false,
+            search_origin: None,
+            plugins: Vec::new(),
+            plugin_commands: HashMap::new(),
+            plugin_requests: Vec::new(),
+            language: None,
             client,
             file,
         }
     }
 
+    /// The file being edited, if any.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Number of lines of the buffer.
+    pub fn line_count(&self) -> u64 {
+        self.cache.height()
+    }
+
+    /// Whether the buffer has unsaved changes.
+    pub fn is_dirty(&self) -> bool {
+        !self.pristine
+    }
+
     pub fn update_cache(&mut self, update: Update) {
         info!("updating cache");
+        self.pristine = update.pristine;
+        self.updates += 1;
         self.cache.update(update)
     }
 
     pub fn set_cursor(&mut self, line: u64, column: u64) {
         self.cursor = Cursor { line, column };
         self.window.set_cursor(&self.cursor);
+
+        // After a jump (to a given line for instance), the cursor may be
+        // on a line we don't have: ask the core for the lines around it.
+        let first_line = self.cache.before();
+        let last_line = first_line + self.cache.lines().len() as u64;
+        if line < first_line || line >= last_line {
+            let height = u64::from(self.window.size());
+            self.client.scroll(line.saturating_sub(height), line + height);
+        }
     }
 
     pub fn config_changed(&mut self, changes: ConfigChanges) {
         if let Some(tab_size) = changes.tab_size {
             self.cfg.tab_size = tab_size as u16;
+            self.core_options
+                .insert("tab_size", OptionValue::Number(tab_size));
+        }
+        if let Some(translate) = changes.translate_tabs_to_spaces {
+            self.core_options
+                .insert("translate_tabs_to_spaces", OptionValue::Bool(translate));
+        }
+    }
+
+    /// Get the value of an option for this buffer, if it is known.
+    pub fn option(&self, spec: &OptionSpec) -> Option<OptionValue> {
+        if spec.core {
+            self.core_options.get(spec.name).cloned()
+        } else {
+            self.cfg.get(spec.name)
+        }
+    }
+
+    /// Change an option for this buffer. Core options are sent to the
+    /// core, and only apply to this buffer.
+    pub fn set_option(&mut self, spec: &'static OptionSpec, value: OptionValue) {
+        if spec.core {
+            self.client
+                .modify_config(json!({ spec.name: value.to_json() }));
+        }
+        self.option_changed(spec, value);
+    }
+
+    /// Record the new value of an option, that was changed for all the
+    /// buffers.
+    pub fn option_changed(&mut self, spec: &'static OptionSpec, value: OptionValue) {
+        if spec.core {
+            self.core_options.insert(spec.name, value);
+        } else {
+            self.cfg.set(spec.name, value);
+        }
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Record the language the core detected, or was asked to use.
+    pub fn language_changed(&mut self, language: String) {
+        self.language = Some(language);
+    }
+
+    /// Ask the core to change the language of the buffer.
+    pub fn set_language(&mut self, language: &str) {
+        self.client.set_language(language);
+    }
+
+    pub fn plugins(&self) -> &[Plugin] {
+        &self.plugins
+    }
+
+    pub fn set_plugins(&mut self, plugins: Vec<Plugin>) {
+        self.plugins = plugins;
+    }
+
+    /// Record that a plugin started. Return whether the user asked for
+    /// it.
+    pub fn plugin_started(&mut self, name: &str) -> bool {
+        match self.plugins.iter_mut().find(|plugin| plugin.name == name) {
+            Some(plugin) => plugin.running = true,
+            None => self.plugins.push(Plugin {
+                name: name.to_string(),
+                running: true,
+            }),
+        }
+        self.take_plugin_request(name)
+    }
+
+    /// Record that a plugin stopped, and forget its commands. Return
+    /// whether the user asked for it: otherwise the plugin crashed.
+    pub fn plugin_stopped(&mut self, name: &str) -> bool {
+        if let Some(plugin) = self.plugins.iter_mut().find(|plugin| plugin.name == name) {
+            plugin.running = false;
+        }
+        self.plugin_commands.remove(name);
+        self.take_plugin_request(name)
+    }
+
+    fn take_plugin_request(&mut self, name: &str) -> bool {
+        let requested = self.plugin_requests.iter().any(|request| request == name);
+        self.plugin_requests.retain(|request| request != name);
+        requested
+    }
+
+    pub fn set_plugin_commands(&mut self, plugin: String, commands: Vec<String>) {
+        self.plugin_commands.insert(plugin, commands);
+    }
+
+    /// Names of the commands provided by the plugins.
+    pub fn plugin_commands(&self) -> Vec<String> {
+        let mut commands: Vec<String> = self.plugin_commands.values().flatten().cloned().collect();
+        commands.sort();
+        commands.dedup();
+        commands
+    }
+
+    /// Start or stop a plugin for this buffer.
+    pub fn toggle_plugin(&mut self, action: PluginAction, name: &str) -> Result<(), String> {
+        let start = action == PluginAction::Start;
+        let running = match self.plugins.iter().find(|plugin| plugin.name == name) {
+            Some(plugin) => plugin.running,
+            None => return Err(format!("no plugin named {}", name)),
+        };
+        if running == start {
+            let state = if running { "running" } else { "stopped" };
+            return Err(format!("plugin {} is already {}", name, state));
+        }
+        self.plugin_requests.push(name.to_string());
+        if start {
+            self.client.start_plugin(name);
+        } else {
+            self.client.stop_plugin(name);
+        }
+        Ok(())
+    }
+
+    /// Run a command provided by a plugin. Return `false` if no plugin
+    /// provides it.
+    pub fn plugin_command(&mut self, command: &str) -> bool {
+        let plugin = self
+            .plugin_commands
+            .iter()
+            .find(|&(_, commands)| commands.iter().any(|c| c == command))
+            .map(|(plugin, _)| plugin.clone());
+        match plugin {
+            Some(plugin) => {
+                self.client.plugin_command(&plugin, command);
+                true
+            }
+            None => false,
         }
     }
 
@@ -62,12 +301,15 @@ impl View {
         styles: &HashMap<u64, Style>,
     ) -> Result<(), Error> {
         self.update_window();
+        self.fit_cursor();
         self.render_lines(w, styles)?;
+        self.render_secondary_cursors(w)?;
         self.render_cursor(w);
         Ok(())
     }
 
-    pub fn resize(&mut self, height: u16) {
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
         self.window.resize(height);
         self.update_window();
         let top = self.cache.before() + self.window.start();
@@ -87,8 +329,57 @@ impl View {
         self.client.insert_tab()
     }
 
-    pub fn save(&mut self) {
-        self.client.save(self.file.as_ref().unwrap())
+    pub fn paste(&mut self, text: &str) {
+        self.client.paste(text)
+    }
+
+    pub fn copy(&mut self, clipboard_tx: UnboundedSender<String>) {
+        self.client.copy(clipboard_tx)
+    }
+
+    pub fn cut(&mut self, clipboard_tx: UnboundedSender<String>) {
+        self.client.cut(clipboard_tx)
+    }
+
+    /// Number of updates received from the core.
+    pub fn updates(&self) -> u64 {
+        self.updates
+    }
+
+    /// Undo the last edit. The core keeps the undo history, and sends
+    /// no update if there is nothing to undo: a message saying so is
+    /// then sent to `synced_tx`.
+    pub fn undo(&mut self, synced_tx: UnboundedSender<Synced>) {
+        let message = Message::Info("Nothing to undo".into());
+        self.client.undo(self.updates, message, synced_tx);
+    }
+
+    /// Redo the last undone edit, or send a message saying there is
+    /// nothing to redo to `synced_tx`.
+    pub fn redo(&mut self, synced_tx: UnboundedSender<Synced>) {
+        let message = Message::Info("Nothing to redo".into());
+        self.client.redo(self.updates, message, synced_tx);
+    }
+
+    /// Save the buffer, under a new file name if one is given. Return
+    /// `false` if the buffer has no file name and none is given. If
+    /// `synced_tx` is given, the result of the save is sent to it, as
+    /// for `Client::save`.
+    pub fn save(
+        &mut self,
+        file: Option<String>,
+        synced_tx: Option<(u64, UnboundedSender<Synced>)>,
+    ) -> bool {
+        if file.is_some() {
+            self.file = file;
+        }
+        match self.file {
+            Some(ref file) => {
+                self.client.save(file, synced_tx);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn back(&mut self) {
@@ -123,8 +414,226 @@ impl View {
         self.client.down()
     }
 
+    pub fn move_word_left(&mut self) {
+        self.client.move_word_left()
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.client.move_word_right()
+    }
+
+    pub fn paragraph_up(&mut self) {
+        self.client.paragraph_up()
+    }
+
+    pub fn paragraph_down(&mut self) {
+        self.client.paragraph_down()
+    }
+
+    pub fn document_start(&mut self) {
+        self.client.document_start()
+    }
+
+    pub fn document_end(&mut self) {
+        self.client.document_end()
+    }
+
+    /// Move the cursor to the given line, and optionally column. Lines
+    /// and columns are numbered from 1, like in the gutter.
+    pub fn goto(&mut self, line: u64, column: Option<u64>) {
+        match column {
+            Some(column) => self.client.click(line - 1, column - 1),
+            None => self.client.goto_line(line - 1),
+        }
+    }
+
+    pub fn select_left(&mut self) {
+        self.client.select_left()
+    }
+
+    pub fn select_right(&mut self) {
+        self.client.select_right()
+    }
+
+    pub fn select_up(&mut self) {
+        self.client.select_up()
+    }
+
+    pub fn select_down(&mut self) {
+        self.client.select_down()
+    }
+
+    pub fn select_word_left(&mut self) {
+        self.client.select_word_left()
+    }
+
+    pub fn select_word_right(&mut self) {
+        self.client.select_word_right()
+    }
+
+    pub fn select_home(&mut self) {
+        self.client.select_home()
+    }
+
+    pub fn select_end(&mut self) {
+        self.client.select_end()
+    }
+
+    pub fn select_page_up(&mut self) {
+        self.client.select_page_up()
+    }
+
+    pub fn select_page_down(&mut self) {
+        self.client.select_page_down()
+    }
+
+    pub fn select_document_start(&mut self) {
+        self.client.select_document_start()
+    }
+
+    pub fn select_document_end(&mut self) {
+        self.client.select_document_end()
+    }
+
+    pub fn add_cursor_above(&mut self) {
+        self.client.add_cursor_above()
+    }
+
+    pub fn add_cursor_below(&mut self) {
+        self.client.add_cursor_below()
+    }
+
+    pub fn select_next_occurrence(&mut self) {
+        self.client.select_next_occurrence()
+    }
+
+    pub fn select_all_occurrences(&mut self) {
+        self.client.select_all_occurrences()
+    }
+
+    pub fn collapse_selections(&mut self) {
+        self.client.collapse_selections()
+    }
+
+    /// Select the lines of a range, or fail if the range doesn't fit in
+    /// the buffer.
+    pub fn select_lines(&mut self, range: Range) -> Result<(), String> {
+        let (first, last) = range.resolve(self.cursor.line + 1, self.line_count())?;
+        self.client.select_lines(first - 1, last - 1);
+        Ok(())
+    }
+
+    pub fn sort_lines(&mut self) {
+        self.client.sort_lines()
+    }
+
+    pub fn indent(&mut self) {
+        self.client.indent()
+    }
+
+    pub fn outdent(&mut self) {
+        self.client.outdent()
+    }
+
+    pub fn find(&mut self, query: &SearchQuery) {
+        self.report_match = true;
+        self.client.find(query)
+    }
+
+    pub fn find_next(&mut self) {
+        self.report_match = true;
+        self.client.find_next()
+    }
+
+    pub fn find_prev(&mut self) {
+        self.report_match = true;
+        self.client.find_prev()
+    }
+
+    /// Start an incremental search, remembering where the cursor is.
+    pub fn start_search(&mut self) {
+        self.search_origin = Some((self.cursor.line, self.cursor.column));
+    }
+
+    /// Search for the text typed so far, from where the incremental
+    /// search started.
+    pub fn update_search(&mut self, text: &str) {
+        if let Some((line, column)) = self.search_origin {
+            self.client.click(line, column);
+        }
+        if text.is_empty() {
+            self.hide_matches();
+        } else {
+            self.find(&SearchQuery::new(text.to_string(), ""));
+        }
+    }
+
+    /// End the incremental search, either keeping the current match or
+    /// moving the cursor back to where it was.
+    pub fn end_search(&mut self, keep: bool) {
+        if let Some((line, column)) = self.search_origin.take() {
+            if !keep {
+                self.client.click(line, column);
+                self.hide_matches();
+            }
+        }
+    }
+
+    /// Search for a query and select its nearest match, to be replaced
+    /// with `replace_next` or `replace_all`.
+    pub fn replace(&mut self, query: &SearchQuery, replacement: &str) {
+        self.find(query);
+        self.client.replace(replacement);
+    }
+
+    /// Replace the selected match, and select the next one.
+    pub fn replace_next(&mut self) {
+        self.client.replace_next();
+        self.find_next();
+    }
+
+    pub fn replace_all(&mut self) {
+        self.client.replace_all();
+    }
+
+    pub fn hide_matches(&mut self) {
+        self.report_match = false;
+        self.client.highlight_find(false)
+    }
+
+    pub fn set_find_status(&mut self, status: FindStatus) {
+        self.search = status.queries.into_iter().next().map(|query| Search {
+            text: query.chars.unwrap_or_default(),
+            matches: query.matches,
+            lines: query.lines,
+        });
+    }
+
+    /// If the user just moved to a match, describe it, e.g. "match 3
+    /// of 17". Matches are only known by their line, so the first
+    /// match of the line of the cursor is assumed.
+    pub fn match_status(&self) -> Option<Message> {
+        let search = match self.search {
+            Some(ref search) if self.report_match => search,
+            _ => return None,
+        };
+        if search.matches == 0 {
+            return Some(Message::Error(format!("no match for {}", search.text)));
+        }
+        let line = self.cursor.line + 1;
+        Some(Message::Info(
+            match search.lines.iter().position(|l| *l == line) {
+                Some(i) => format!("match {} of {}", i + 1, search.matches),
+                None => format!("{} matches", search.matches),
+            },
+        ))
+    }
+
     pub fn toggle_line_numbers(&mut self) {
-        self.cfg.display_gutter = !self.cfg.display_gutter;
+        self.cfg.gutter = match self.cfg.gutter {
+            GutterMode::Off => GutterMode::Absolute,
+            _ => GutterMode::Off,
+        };
     }
 
     fn update_window(&mut self) {
@@ -147,39 +656,72 @@ impl View {
     }
 
     fn get_click_location(&self, x: u64, y: u64) -> (u64, u64) {
-        let lineno = x + self.cache.before() + self.window.start();
-        if let Some(line) = self.cache.lines().get(x as usize) {
-            if y < u64::from(self.cfg.gutter_size) {
+        let (index, row) = self.line_at_row(x);
+        let lineno = self.cache.before() + index;
+        let line = match self.cache.lines().get(index as usize) {
+            Some(line) => line,
+            None => {
+                warn!("no line at index {} found in cache", index);
                 return (lineno, 0);
             }
-            let mut text_len: u16 = 0;
-            for (idx, c) in line.text.chars().enumerate() {
-                let char_width = self.translate_char_width(text_len, c);
-                text_len += char_width;
-                if u64::from(text_len) >= y {
-                    // If the character at idx is wider than one column,
-                    // the click occurred within the character. Otherwise,
-                    // the click occurred on the character at idx + 1
-                    if char_width > 1 {
-                        return (lineno as u64, (idx - self.cfg.gutter_size as usize) as u64);
-                    } else {
-                        return (
-                            lineno as u64,
-                            (idx - self.cfg.gutter_size as usize) as u64 + 1,
-                        );
-                    }
-                }
+        };
+        let gutter = u64::from(self.gutter_width());
+        if y < gutter {
+            return (lineno, 0);
+        }
+        // Count the columns of the rows above, if the line is wrapped.
+        let target = row * u64::from(self.text_width()) + y - gutter;
+        let mut width: u16 = 0;
+        for (idx, c) in line.text.chars().enumerate() {
+            width += self.translate_char_width(width, c);
+            // Clicking a character puts the cursor before it, even if
+            // the character is several columns wide.
+            if u64::from(width) > target {
+                return (lineno, idx as u64);
             }
-            return (lineno, line.text.len() as u64 + 1);
-        } else {
-            warn!("no line at index {} found in cache", x);
-            return (x, y);
         }
+        (lineno, line.text.chars().count() as u64)
     }
 
-    fn click(&mut self, x: u64, y: u64) {
+    /// Return how many times in a row the given position was clicked:
+    /// 1 for a simple click, 2 for a double-click and 3 for a
+    /// triple-click. A fourth click starts over.
+    fn click_count(&mut self, line: u64, column: u64) -> u8 {
+        let now = Instant::now();
+        let count = match self.last_click {
+            Some(ref click)
+                if click.line == line
+                    && click.column == column
+                    && now.duration_since(click.time) < MULTI_CLICK_DELAY =>
+            {
+                click.count % 3 + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some(Click {
+            time: now,
+            line,
+            column,
+            count,
+        });
+        count
+    }
+
+    fn click(&mut self, x: u64, y: u64, modifiers: Modifiers) {
         let (line, column) = self.get_click_location(x, y);
-        self.client.click(line, column);
+        if modifiers.shift {
+            self.client.range_select(line, column);
+        } else if y < u64::from(self.gutter_width()) {
+            // Clicking the gutter selects the whole line. Dragging from
+            // there extends the selection line by line.
+            self.client.line_select(line, column);
+        } else {
+            match self.click_count(line, column) {
+                1 => self.client.click(line, column),
+                2 => self.client.word_select(line, column),
+                _ => self.client.line_select(line, column),
+            }
+        }
     }
 
     fn drag(&mut self, x: u64, y: u64) {
@@ -187,7 +729,23 @@ impl View {
         self.client.drag(line, column);
     }
 
-    pub fn handle_input(&mut self, event: Event) {
+    /// Handle an input event, repeated `count` times. Characters are
+    /// inserted in a single edit rather than one edit per repetition.
+    pub fn handle_input(&mut self, event: Event, count: usize) {
+        self.report_match = false;
+        match event {
+            Event::Key(Key::Char(c)) if count > 1 && c != '\n' && c != '\t' => {
+                self.client.insert_str(&c.to_string().repeat(count))
+            }
+            event => {
+                for _ in 0..count {
+                    self.handle_event(event.clone());
+                }
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
         match event {
             Event::Key(key) => match key {
                 Key::Char(c) => match c {
@@ -196,10 +754,14 @@ impl View {
                     _ => self.insert(c),
                 },
                 Key::Ctrl(c) => match c {
-                    'w' => self.save(),
                     'h' => self.back(),
+                    'd' => self.select_next_occurrence(),
+                    'n' => self.find_next(),
+                    'p' => self.find_prev(),
                     _ => error!("un-handled input ctrl+{}", c),
                 },
+                Key::Alt('d') => self.select_all_occurrences(),
+                Key::Esc => self.collapse_selections(),
                 Key::Backspace => self.back(),
                 Key::Delete => self.delete(),
                 Key::Left => self.client.left(),
@@ -212,17 +774,59 @@ impl View {
                 Key::PageDown => self.page_down(),
                 k => error!("un-handled key {:?}", k),
             },
-            Event::Mouse(mouse_event) => match mouse_event {
-                MouseEvent::Press(press_event, y, x) => match press_event {
-                    MouseButton::Left => self.click(u64::from(x) - 1, u64::from(y) - 1),
-                    MouseButton::WheelUp => self.client.up(),
-                    MouseButton::WheelDown => self.client.down(),
-                    button => error!("un-handled button {:?}", button),
-                },
-                MouseEvent::Release(..) => {}
-                MouseEvent::Hold(y, x) => self.drag(u64::from(x) - 1, u64::from(y) - 1),
+            Event::Mouse(mouse_event) => self.handle_mouse(mouse_event, Modifiers::default()),
+            Event::Unsupported(ref seq) => match parse_unsupported(seq) {
+                Some(InputEvent::Key(key, modifiers)) => self.handle_modified_key(key, modifiers),
+                Some(InputEvent::Mouse(mouse_event, modifiers)) => {
+                    self.handle_mouse(mouse_event, modifiers)
+                }
+                None => error!("un-handled event {:?}", event),
             },
-            ev => error!("un-handled event {:?}", ev),
+        }
+    }
+
+    /// Alt with up and down adds a cursor on the line above or below.
+    /// Ctrl moves the cursor by words (left and right), paragraphs (up
+    /// and down) or to the document boundaries (Home and End). Shift
+    /// does the same but extends the selection.
+    fn handle_modified_key(&mut self, key: Key, modifiers: Modifiers) {
+        match (key, modifiers.shift, modifiers.ctrl) {
+            (Key::Up, false, false) if modifiers.alt => self.add_cursor_above(),
+            (Key::Down, false, false) if modifiers.alt => self.add_cursor_below(),
+            (Key::Left, false, true) => self.move_word_left(),
+            (Key::Right, false, true) => self.move_word_right(),
+            (Key::Up, false, true) => self.paragraph_up(),
+            (Key::Down, false, true) => self.paragraph_down(),
+            (Key::Home, false, true) => self.document_start(),
+            (Key::End, false, true) => self.document_end(),
+            (Key::Left, true, false) => self.select_left(),
+            (Key::Right, true, false) => self.select_right(),
+            (Key::Up, true, _) => self.select_up(),
+            (Key::Down, true, _) => self.select_down(),
+            (Key::Left, true, true) => self.select_word_left(),
+            (Key::Right, true, true) => self.select_word_right(),
+            (Key::Home, true, false) => self.select_home(),
+            (Key::End, true, false) => self.select_end(),
+            (Key::Home, true, true) => self.select_document_start(),
+            (Key::End, true, true) => self.select_document_end(),
+            (Key::PageUp, true, _) => self.select_page_up(),
+            (Key::PageDown, true, _) => self.select_page_down(),
+            (key, _, _) => error!("un-handled key {:?} with {:?}", key, modifiers),
+        }
+    }
+
+    fn handle_mouse(&mut self, event: MouseEvent, modifiers: Modifiers) {
+        match event {
+            MouseEvent::Press(press_event, y, x) => match press_event {
+                MouseButton::Left => self.click(u64::from(x) - 1, u64::from(y) - 1, modifiers),
+                MouseButton::WheelUp => self.client.up(),
+                MouseButton::WheelDown => self.client.down(),
+                button => error!("un-handled button {:?}", button),
+            },
+            // xi-core has no notion of button release: a drag ends with
+            // the last "drag" edit.
+            MouseEvent::Release(..) => {}
+            MouseEvent::Hold(y, x) => self.drag(u64::from(x) - 1, u64::from(y) - 1),
         }
     }
 
@@ -230,35 +834,21 @@ impl View {
         debug!("rendering lines");
         trace!("current cache\n{:?}", self.cache);
 
-        // Get the lines that are within the displayed window
-        let lines = self
-            .cache
-            .lines()
-            .iter()
-            .skip(self.window.start() as usize)
-            .take(self.window.size() as usize);
-
-        // Draw the valid lines within this range
+        // Draw the valid lines within the displayed window, and empty
+        // lines after the last one to fill the window.
+        let empty = Line::default();
+        let height = u64::from(self.window.size());
         let mut line_strings = String::new();
-        let mut line_no = self.cache.before() + self.window.start();
-        for (line_index, line) in lines.enumerate() {
-            line_strings.push_str(&self.render_line_str(line, Some(line_no), line_index, styles));
-            line_no += 1;
-        }
-
-        // If the number of lines is less than window height
-        // render empty lines to fill the view window.
-        let line_count = self.cache.lines().len() as u16;
-        let win_size = self.window.size();
-        if win_size > line_count {
-            for num in line_count..win_size {
-                line_strings.push_str(&self.render_line_str(
-                    &Line::default(),
-                    None,
-                    num as usize,
-                    styles,
-                ));
-            }
+        let mut row = 0;
+        let mut index = self.window.start();
+        while row < height {
+            let (line, line_no) = match self.cache.lines().get(index as usize) {
+                Some(line) => (line, Some(self.cache.before() + index)),
+                None => (&empty, None),
+            };
+            line_strings.push_str(&self.render_line_str(line, line_no, row, height - row, styles));
+            row += self.line_rows(line);
+            index += 1;
         }
         w.write_all(line_strings.as_bytes())?;
 
@@ -270,43 +860,59 @@ impl View {
         self.cfg.tab_size - (position % self.cfg.tab_size)
     }
 
+    /// Render a line from the given row of the window, on at most
+    /// `max_rows` rows if it is wrapped.
     fn render_line_str(
         &self,
         line: &Line,
         lineno: Option<u64>,
-        line_index: usize,
+        row: u64,
+        max_rows: u64,
         styles: &HashMap<u64, Style>,
     ) -> String {
         let text = self.escape_control_and_add_styles(styles, line);
-        if let Some(line_no) = lineno {
-            if self.cfg.display_gutter {
-                let line_no = (line_no + 1).to_string();
-                let line_no_offset = self.cfg.gutter_size - line_no.len() as u16;
-                format!(
-                    "{}{}{}{}{}",
-                    Goto(line_no_offset, line_index as u16 + 1),
-                    ClearLine,
-                    line_no,
-                    Goto(self.cfg.gutter_size + 1, line_index as u16 + 1),
-                    &text
-                )
-            } else {
-                format!("{}{}{}", Goto(0, line_index as u16 + 1), ClearLine, &text)
+        let gutter = match lineno {
+            Some(line_no) if self.cfg.gutter != GutterMode::Off => {
+                let number = match self.cfg.gutter {
+                    GutterMode::Relative if line_no != self.cursor.line => {
+                        max(line_no, self.cursor.line) - min(line_no, self.cursor.line)
+                    }
+                    _ => line_no + 1,
+                };
+                format!("{:>1$} ", number, self.gutter_width() as usize - 1)
             }
+            _ => String::new(),
+        };
+        let rows = if self.cfg.wrap {
+            split_columns(&text, self.text_width() as usize)
         } else {
-            format!(
-                "{}{}{}",
-                Goto(self.cfg.gutter_size + 1, line_index as u16 + 1),
+            vec![text]
+        };
+        let mut rendered = String::new();
+        for (i, text) in rows.iter().enumerate().take(max_rows as usize) {
+            let y = (row + i as u64) as u16 + 1;
+            rendered.push_str(&format!(
+                "{}{}{}{}{}",
+                Goto(1, y),
                 ClearLine,
-                &text
-            )
+                if i == 0 { &gutter[..] } else { "" },
+                Goto(self.gutter_width() + 1, y),
+                text
+            ));
         }
+        rendered
     }
 
     fn escape_control_and_add_styles(&self, styles: &HashMap<u64, Style>, line: &Line) -> String {
         let mut position: u16 = 0;
         let mut text = String::with_capacity(line.text.capacity());
-        for c in line.text.chars() {
+        let trailing = line.text.trim_end_matches([' ', '\t']).len();
+        for (i, c) in line.text.char_indices() {
+            let visible = match self.cfg.whitespace {
+                Whitespace::Hidden => false,
+                Whitespace::Trailing => i >= trailing,
+                Whitespace::All => true,
+            };
             match c {
                 '\x00'..='\x08' | '\x0a'..='\x1f' | '\x7f' => {
                     // Render in caret notation, i.e. '\x02' is rendered as '^B'
@@ -316,9 +922,18 @@ impl View {
                 }
                 '\t' => {
                     let tab_width = self.tab_width_at_position(position);
-                    text.push_str(&" ".repeat(tab_width as usize));
+                    if visible {
+                        text.push('>');
+                        text.push_str(&" ".repeat(tab_width as usize - 1));
+                    } else {
+                        text.push_str(&" ".repeat(tab_width as usize));
+                    }
                     position += tab_width;
                 }
+                ' ' if visible => {
+                    text.push('.');
+                    position += 1;
+                }
                 _ => {
                     text.push(c);
                     position += 1;
@@ -429,8 +1044,6 @@ impl View {
             );
             return;
         }
-        // Get the line vertical offset so that we know where to draw it.
-        let line_pos = line_idx - self.window.start();
 
         // Calculate the cursor position on the line. The trick is that we know the position within
         // the string, but characters may have various lengths. For the moment, we only handle
@@ -438,20 +1051,170 @@ impl View {
         // tab) are rendered in caret notation and are thus two columns wide. Tabs are
         // variable-width, rounding up to the next tab stop. All other characters are assumed to be
         // one column wide.
-        let column: u16 = line
-            .text
-            .chars()
-            .take(self.cursor.column as usize)
-            .fold(0, |acc, c| acc + self.translate_char_width(acc, c));
+        let column = self.columns(&line.text, self.cursor.column as usize);
+        let (row, column) = self.row_and_column(line, column);
+
+        // Get the line vertical offset so that we know where to draw it.
+        let line_pos = self.rows_between(self.window.start(), line_idx) + row;
 
         // Draw the cursor
-        let cursor_pos = Goto(self.cfg.gutter_size + column + 1, line_pos as u16 + 1);
+        let cursor_pos = Goto(self.gutter_width() + column + 1, line_pos as u16 + 1);
         if let Err(e) = write!(w, "{}", cursor_pos) {
             error!("failed to render cursor: {}", e);
         }
         info!("Cursor rendered at ({}, {})", line_pos, column);
     }
 
+    /// Draw the cursors other than the main one (which is the terminal
+    /// cursor) in reverse video.
+    fn render_secondary_cursors<W: Write>(&self, w: &mut W) -> Result<(), Error> {
+        let height = u64::from(self.window.size());
+        let mut first_row = 0;
+        let lines = self
+            .cache
+            .lines()
+            .iter()
+            .enumerate()
+            .skip(self.window.start() as usize);
+        for (line_idx, line) in lines {
+            if first_row >= height {
+                break;
+            }
+            let line_no = self.cache.before() + line_idx as u64;
+            for &offset in &line.cursor {
+                // Cursor positions are byte offsets, but we need a
+                // character index to get the column.
+                let index = line
+                    .text
+                    .char_indices()
+                    .take_while(|&(i, _)| (i as u64) < offset)
+                    .count();
+                if line_no == self.cursor.line && index as u64 == self.cursor.column {
+                    continue;
+                }
+                let (row, column) = self.row_and_column(line, self.columns(&line.text, index));
+                if first_row + row >= height {
+                    continue;
+                }
+                let c = match line.text.chars().nth(index) {
+                    Some(c) if !c.is_control() => c,
+                    _ => ' ',
+                };
+                write!(
+                    w,
+                    "{}{}{}{}",
+                    Goto(
+                        self.gutter_width() + column + 1,
+                        (first_row + row) as u16 + 1
+                    ),
+                    termion::style::Invert,
+                    c,
+                    termion::style::NoInvert
+                )?;
+            }
+            first_row += self.line_rows(line);
+        }
+        Ok(())
+    }
+
+    /// Number of columns taken by the gutter, if it is shown.
+    fn gutter_width(&self) -> u16 {
+        match self.cfg.gutter {
+            GutterMode::Off => 0,
+            _ => self.cfg.gutter_size,
+        }
+    }
+
+    /// Number of columns left for the text, right of the gutter.
+    fn text_width(&self) -> u16 {
+        max(self.width.saturating_sub(self.gutter_width()), 1)
+    }
+
+    /// Number of columns taken on screen by the first `chars`
+    /// characters of a line.
+    fn columns(&self, text: &str, chars: usize) -> u16 {
+        text.chars()
+            .take(chars)
+            .fold(0, |acc, c| acc + self.translate_char_width(acc, c))
+    }
+
+    /// Number of rows a line takes on screen.
+    fn line_rows(&self, line: &Line) -> u64 {
+        if !self.cfg.wrap {
+            return 1;
+        }
+        let width = u64::from(self.columns(&line.text, usize::MAX));
+        let text_width = u64::from(self.text_width());
+        max(width.div_ceil(text_width), 1)
+    }
+
+    /// Number of rows taken by the lines of the cache from `start` to
+    /// `end` (excluded).
+    fn rows_between(&self, start: u64, end: u64) -> u64 {
+        self.cache
+            .lines()
+            .iter()
+            .skip(start as usize)
+            .take(end.saturating_sub(start) as usize)
+            .map(|line| self.line_rows(line))
+            .sum()
+    }
+
+    /// Get the row, relative to the first row of a line, and the
+    /// column, relative to the start of the text, where a column of the
+    /// line is displayed.
+    fn row_and_column(&self, line: &Line, column: u16) -> (u64, u16) {
+        if !self.cfg.wrap {
+            return (0, column);
+        }
+        let width = self.text_width();
+        // The end of a line that fills its last row stays on this row.
+        if column > 0
+            && column.is_multiple_of(width)
+            && column == self.columns(&line.text, usize::MAX)
+        {
+            return (u64::from(column / width - 1), width);
+        }
+        (u64::from(column / width), column % width)
+    }
+
+    /// Find the line displayed at a row of the window: get its index in
+    /// the cache, and which of its rows it is.
+    fn line_at_row(&self, row: u64) -> (u64, u64) {
+        let mut first_row = 0;
+        let mut index = self.window.start();
+        for line in self.cache.lines().iter().skip(index as usize) {
+            let rows = self.line_rows(line);
+            if row < first_row + rows {
+                return (index, row - first_row);
+            }
+            first_row += rows;
+            index += 1;
+        }
+        (index + row - first_row, 0)
+    }
+
+    /// When lines are wrapped, the window holds fewer lines than it has
+    /// rows: scroll it down until the cursor is visible.
+    fn fit_cursor(&mut self) {
+        if !self.cfg.wrap || self.cursor.line < self.cache.before() {
+            return;
+        }
+        let index = self.cursor.line - self.cache.before();
+        let row = match self.cache.lines().get(index as usize) {
+            Some(line) => {
+                let column = self.columns(&line.text, self.cursor.column as usize);
+                self.row_and_column(line, column).0
+            }
+            None => return,
+        };
+        let height = u64::from(self.window.size());
+        let mut start = self.window.start();
+        while start < index && self.rows_between(start, index) + row >= height {
+            start += 1;
+        }
+        self.window.set_start(start);
+    }
     fn translate_char_width(&self, position: u16, c: char) -> u16 {
         match c {
             // Caret notation means non-tab control characters are two columns wide
@@ -461,3 +1224,34 @@ impl View {
         }
     }
 }
+
+/// Split a line, which may contain escape sequences setting styles,
+/// into rows of the given width.
+fn split_columns(text: &str, width: usize) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut columns = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let row = rows.last_mut().unwrap();
+        if c == '\x1b' {
+            // Escape sequences take no room: copy them up to their
+            // final character.
+            row.push(c);
+            for c in chars.by_ref() {
+                row.push(c);
+                if c != '[' && ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            continue;
+        }
+        if columns == width {
+            rows.push(c.to_string());
+            columns = 1;
+        } else {
+            row.push(c);
+            columns += 1;
+        }
+    }
+    rows
+}
@@ -21,6 +21,12 @@ impl Window {
         info!("new window: {:?}", self);
     }
 
+    /// Make the window start at the given line, e.g. when the lines
+    /// above the cursor take more than one row each.
+    pub fn set_start(&mut self, start: u64) {
+        self.start = start;
+    }
+
     pub fn resize(&mut self, height: u16) {
         self.size = height;
     }